@@ -1,199 +1,769 @@
 mod state;
 mod renderer;
 mod physics;
+mod key;
+mod replay;
+mod controls;
+mod snapshot;
+mod scene;
+mod error;
+mod settings;
+mod watch;
+mod console;
+mod level;
+mod campaign;
 
 use std::{
     error::Error,
+    fs,
+    path::{Path, PathBuf},
     sync::mpsc,
     time,
     collections::VecDeque
 };
 
-use lib::math::vec2;
+use lib::math::{Vec2, vec2, wrap_angle};
 
-use state::State;
+use state::{State, Scene};
 use renderer::Renderer;
+use controls::Action;
+use console::{Command, DebugTarget};
+use level::Level;
+use campaign::{Campaign, Progress};
+
+pub use error::{GameError, ErrorKind};
+pub use renderer::ErrorScreen;
+pub use settings::Settings;
 
 
 pub enum Event {
     Close,
-    Key(glfw::Action, glfw::Key)
+    Key(glfw::Action, glfw::Key, time::Instant),
+    GamepadButton(glfw::Action, glfw::GamepadButton, time::Instant),
+    GamepadStick(Vec2, time::Instant),
+    /// A character was typed.
+    Char(char, time::Instant),
+    MouseButton(glfw::Action, glfw::MouseButton, time::Instant),
+    /// The cursor moved to the given position in pixels from the top left corner of the screen.
+    CursorPos(Vec2, time::Instant),
+    CursorLeft,
+    /// The framebuffer was resized to the given width and height.
+    Resize(i32, i32),
+    /// The window was switched to fullscreen or back to windowed mode.
+    Fullscreen(bool),
+    /// The window was resized to the given width and height while not fullscreen.
+    WindowSize(u32, u32)
+}
+
+const STICK_DEAD_ZONE: f32 = 0.25;
+/// How much energy a piece of food gives.
+const FOOD_ENERGY: u32 = 200;
+/// How many seconds can be rewound.
+const HISTORY_SECONDS: u32 = 10;
+/// How fast the direction keys move the editor's camera, in units per second.
+const EDITOR_PAN_SPEED: f32 = 15.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Button {
+    Key(glfw::Key),
+    Gamepad(glfw::GamepadButton)
+}
+
+/// Settings that are fixed for the lifetime of a game.
+#[derive(Clone)]
+pub struct Config {
+    /// A level file to play instead of the campaign.
+    pub level: Option<String>,
+    /// The directory with the campaign's manifest and levels.
+    pub levels: String,
+    pub step_rate: u32,
+    pub cat_length: usize
 }
 
 pub struct Game {
+    config: Config,
     last_update: time::Instant,
+    step_time: time::Duration,
     event_receiver: mpsc::Receiver<Event>,
+    pending_events: VecDeque<Event>,
+    step_index: u64,
+    recorder: Option<replay::Recorder>,
+    player: Option<replay::Player>,
+    settings: Settings,
+    /// The level, kept so that restarting uses the last reloaded version.
+    level: Level,
+    /// The campaign, unless a single level file is played, and the index of the level in it.
+    campaign: Option<Campaign>,
+    level_index: usize,
+    /// Steps simulated since the level started, for timing it.
+    level_steps: u64,
+    /// The saved progress, unless it can't be saved because the game is headless or the progress file is broken.
+    progress: Option<Progress>,
+    watcher: Option<watch::Watcher>,
+    /// Whether the mouse was dragging on the last step, so that letting go is only recorded once.
+    dragging: bool,
+    history: VecDeque<snapshot::Snapshot>,
+    history_steps: usize,
     state: State,
-    renderer: Renderer,
+    renderer: Option<Renderer>,
     physics: physics::World
 }
 
 
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            level: None,
+            levels: "levels".to_string(),
+            step_rate: 480,
+            cat_length: 30
+        }
+    }
+}
+
+
 impl Game {
-    pub fn new(event_receiver: mpsc::Receiver<Event>) -> Result<Game, Box<dyn Error>> {
-        let tiles = include_str!("level.txt").chars()
-            .scan(vec2(0.0, 0.0), |p, c| {
-                match c {
-                    ' ' => {
-                        p.x += 1.0;
-                        Some(None)
-                    }
-                    '\n' => {
-                        p.x = 0.0;
-                        p.y -= 1.0;
-                        Some(None)
-                    }
-                    '\r' => Some(None),
-                    _ => {
-                        let tile_p = *p;
-                        p.x += 1.0;
-                        Some(Some((tile_p, c)))
-                    }
+    pub fn new(config: &Config, settings: Settings, event_receiver: mpsc::Receiver<Event>) -> Result<Game, GameError> {
+        let mut game = Self::new_headless(config, settings, event_receiver)?;
+        game.renderer = Some(Renderer::new()?);
+        // Headless games don't reload files, so that replays stay deterministic.
+        let mut watcher = watch::Watcher::new();
+        match (&config.level, &game.campaign) {
+            (Some(file), _) => watcher.watch(file),
+            (None, Some(campaign)) => {
+                for index in 0..campaign.levels.len() {
+                    watcher.watch(campaign.level_file(index));
                 }
-            }).filter_map(|tile| tile);
-
-        let boxes: Vec<_> = tiles.clone().filter_map(|tile| {
-            if tile.1 == 'X' { Some(tile.0) }
-            else { None }
-        }).collect();
-
-        let p = tiles.clone().find_map(|tile| {
-            if tile.1 == 'P' { Some(tile.0) }
-            else { None }
-        }).unwrap();
-        let path: VecDeque<_> = (0..30).map(|x|
-            vec2(
-                x as f32 * 0.1 + 2.0,
-                0.0
-            ) + p
-        ).collect();
-        let tail: VecDeque<_> = (0..20).map(|x|
-            vec2(
-                x as f32 * -0.1 + 1.6,
-                0.0
-            ) + p
-        ).collect();
-        
-        let state = State {
+            }
+            (None, None) => {}
+        }
+        for file in Renderer::watched_files() {
+            watcher.watch(file);
+        }
+        game.watcher = Some(watcher);
+        // A broken progress file is left alone, instead of being overwritten when a level is completed.
+        game.progress = match Progress::load() {
+            Ok(progress) => Some(progress),
+            Err(error) => {
+                GameError::new(ErrorKind::Config, "loading the progress", error).report();
+                None
+            }
+        };
+        scene::set(&mut game.state, Scene::Title);
+        Ok(game)
+    }
+
+
+    /// Creates a game without a renderer, so no GL context is needed.
+    pub fn new_headless(config: &Config, settings: Settings, event_receiver: mpsc::Receiver<Event>)
+        -> Result<Game, GameError>
+    {
+        let campaign = match &config.level {
+            Some(_) => None,
+            None => {
+                let context = format!("loading the campaign {}", config.levels);
+                let campaign = Campaign::load(Path::new(&config.levels))
+                    .map_err(|error| GameError::new(ErrorKind::Level, &context, error))?;
+                Some(campaign)
+            }
+        };
+        let level = Self::load_level(&Self::level_file(config, campaign.as_ref(), 0))?;
+        let (ground, cat) = Self::start_level(&level, config);
+
+        let mut state = State {
+            scenes: vec![Scene::Gameplay],
+            menu: state::Menu {
+                title: "",
+                items: Vec::new(),
+                selected: 0
+            },
             paused: false,
+            rewinding: false,
+            time_scale: 1.0,
             input: state::Input {
                 left: false,
                 right: false,
                 up: false,
                 down: false,
+                stick: None,
                 turn: false,
                 extend: false,
                 contract: false,
                 fly: false,
                 toggle_pause: false,
                 step: false,
-                toggle_debug_physics: false,
-                toggle_debug_physics_shapes: false,
-                toggle_debug_physics_joints: false,
-                toggle_debug_physics_aabbs: false,
-                toggle_debug_physics_transforms: false,
-                toggle_debug_physics_contacts: false
+                save_snapshot: false,
+                load_snapshot: false,
+                rewind: false,
+                menu: false,
+                confirm: false,
+                menu_up: false,
+                menu_down: false,
+                toggle_profiler: false,
+                toggle_vsync: false,
+                zoom_in: false,
+                zoom_out: false,
+                slow_down: false,
+                speed_up: false,
+                toggle_console: false,
+                toggle_editor: false,
+                save_level: false,
+                next_glyph: false,
+                previous_glyph: false,
+                cursor: None,
+                drag: false,
+                start_drag: false,
+                erase: false
             },
             debug: state::DebugInfo {
                 shapes: VecDeque::new(),
                 frames: VecDeque::new(),
                 skipped_steps: false,
                 paused: false,
+                rewinding: false,
+                time_scale: 1.0,
                 show_physics: false,
-                physics_flags: state::DebugPhysics::all()
+                physics_flags: settings.debug_physics,
+                show_profiler: false,
+                profile: state::Profile {
+                    current: [time::Duration::from_secs(0); state::PROFILE_SECTIONS],
+                    frames: VecDeque::new()
+                },
+                reload_error: None
             },
-            gui: state::GUI {
-                cat_energy: 0
+            screen: state::Screen {
+                width: 1280,
+                height: 720
             },
-            ground: state::Ground {
-                boxes,
-                dirty: state::DirtyFlags::all()
+            camera: state::Camera {
+                position: vec2(0.0, 0.0),
+                zoom: 1.0
             },
-            cat: state::Cat {
-                direction: None,
-                turning: false,
-                extending: false,
-                contracting: false,
-                flying: false,
-                path,
-                tail,
-                grab_d: None,
-                walk_phase: 0.0,
-                energy: 1000
-            }
+            console: state::Console {
+                open: false,
+                input: String::new(),
+                lines: VecDeque::new(),
+                commands: Vec::new(),
+                recalled: None,
+                skip_char: false
+            },
+            editor: state::Editor {
+                open: false,
+                glyph: 'X',
+                glyph_name: level::glyph_name('X'),
+                cursor: None,
+                camera: level.spawn,
+                spawn: level.spawn
+            },
+            gui: state::GUI {
+                cat_energy: 0,
+                level_time: 0.0,
+                best_time: None
+            },
+            steering: settings.steering,
+            ground,
+            props: Vec::new(),
+            cat
         };
 
-        let physics = physics::World::new(&state);
+        let physics = physics::World::new(&mut state);
         
+        let step_time = time::Duration::from_secs(1) / config.step_rate;
         Ok(Game {
+            config: config.clone(),
             last_update: time::Instant::now(),
+            step_time,
             event_receiver,
+            pending_events: VecDeque::new(),
+            step_index: 0,
+            recorder: None,
+            player: None,
+            settings,
+            level,
+            campaign,
+            level_index: 0,
+            level_steps: 0,
+            progress: None,
+            watcher: None,
+            dragging: false,
+            history: VecDeque::new(),
+            history_steps: (HISTORY_SECONDS * config.step_rate) as usize,
             state,
-            renderer: Renderer::new()?,
+            renderer: None,
             physics
         })
     }
 
 
-    pub fn update(&mut self) -> Result<bool, Box<dyn Error>> {
-        let step_time = time::Duration::from_secs(1) / 480;
-        let max_step = step_time * 48;
-        let mut delta_time = self.last_update.elapsed();
-        self.last_update += delta_time;
+    fn load_level(file: &Path) -> Result<Level, GameError> {
+        let context = format!("loading the level {}", file.display());
+        Level::load(file).map_err(|error| GameError::new(ErrorKind::Level, &context, error))
+    }
+
+
+    /// The file of a level of the campaign, or the single level file that is played instead.
+    fn level_file(config: &Config, campaign: Option<&Campaign>, index: usize) -> PathBuf {
+        match (&config.level, campaign) {
+            (None, Some(campaign)) => campaign.level_file(index),
+            (Some(file), _) => PathBuf::from(file),
+            (None, None) => unreachable!("a campaign is loaded unless a level file is played")
+        }
+    }
+
+
+    /// Creates the ground of a level and the cat at its spawn.
+    fn start_level(level: &Level, config: &Config) -> (state::Ground, state::Cat) {
+        let p = level.spawn;
+        let path: VecDeque<_> = (0..config.cat_length).map(|x|
+            vec2(
+                x as f32 * 0.1 + 2.0,
+                0.0
+            ) + p
+        ).collect();
+        let tail: VecDeque<_> = (0..20).map(|x|
+            vec2(
+                x as f32 * -0.1 + 1.6,
+                0.0
+            ) + p
+        ).collect();
+
+        let ground = Self::level_ground(level);
+        let cat = state::Cat {
+            direction: None,
+            speed: 0.0,
+            turning: false,
+            extending: false,
+            contracting: false,
+            flying: false,
+            previous_path: path.clone(),
+            previous_tail: tail.clone(),
+            path,
+            tail,
+            grab_d: None,
+            walk_phase: 0.0,
+            energy: 1000
+        };
+
+        info!("loaded {} boxes and {} other tiles, spawn at {:?}", ground.boxes.len(), ground.tiles.len(), p);
+        (ground, cat)
+    }
+
+
+    fn level_ground(level: &Level) -> state::Ground {
+        state::Ground {
+            boxes: level.boxes.clone(),
+            tiles: level.tiles.clone(),
+            food: level.food.clone(),
+            exit: level.exit,
+            props: level.props.clone(),
+            dirty: state::DirtyFlags::all()
+        }
+    }
+
+
+    /// Starts the level over, keeping the settings and debug state.
+    fn restart(&mut self) -> Result<(), GameError> {
+        let (ground, cat) = Self::start_level(&self.level, &self.config);
+        self.state.ground = ground;
+        self.state.cat = cat;
+        self.state.paused = false;
+        self.state.editor.open = false;
+        self.state.editor.spawn = self.level.spawn;
+        self.physics = physics::World::new(&mut self.state);
+        self.history.clear();
+        self.level_steps = 0;
+        Ok(())
+    }
+
+
+    /// Loads a level of the campaign and starts it.
+    fn play_level(&mut self, index: usize) -> Result<(), GameError> {
+        if self.campaign.is_some() {
+            self.level = Self::load_level(&Self::level_file(&self.config, self.campaign.as_ref(), index))?;
+            self.level_index = index;
+            info!("playing level {} of the campaign", index + 1);
+        }
+        self.restart()
+    }
+
+
+    /// Saves the time and unlocks the next level when the cat reaches the exit.
+    fn complete_level(&mut self) {
+        let seconds = self.level_steps as f32 * self.step_time.as_secs_f32();
+        info!("completed the level in {:.2} s", seconds);
+        let (campaign, progress) = match (&self.campaign, &mut self.progress) {
+            (Some(campaign), Some(progress)) => (campaign, progress),
+            _ => return
+        };
+        if progress.complete(self.level_index, &campaign.levels[self.level_index], seconds) {
+            info!("new best time");
+        }
+        if let Err(error) = progress.save() {
+            warn!("failed to save the progress: {}", error);
+        }
+    }
+
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+
+    /// Records every input event to a replay file.
+    pub fn record(&mut self, file: &str) -> Result<(), GameError> {
+        let context = format!("creating the replay file {}", file);
+        let recorder = replay::Recorder::new(file).map_err(|error| GameError::new(ErrorKind::Other, &context, error))?;
+        self.recorder = Some(recorder);
+        // Replays start in gameplay, so that they line up with headless playback.
+        scene::set(&mut self.state, Scene::Gameplay);
+        Ok(())
+    }
+
+
+    /// Plays back input events from a replay file instead of reading them from the event receiver.
+    pub fn play(&mut self, file: &str) -> Result<(), GameError> {
+        let context = format!("loading the replay file {}", file);
+        let player = replay::Player::new(file).map_err(|error| GameError::new(ErrorKind::Other, &context, error))?;
+        self.player = Some(player);
+        scene::set(&mut self.state, Scene::Gameplay);
+        Ok(())
+    }
+
+
+    pub fn update(&mut self) -> Result<bool, GameError> {
+        self.hot_reload();
+
+        let step_time = self.step_time;
+        let max_step = time::Duration::from_secs(1) / 10;
+        let now = time::Instant::now();
+        let mut delta_time = now - self.last_update;
         if delta_time > max_step {
+            // Events from the skipped time are applied on the first step.
+            self.last_update = now - max_step;
             delta_time = max_step;
             self.state.debug.skipped_steps = true;
         }
         else {
             self.state.debug.skipped_steps = false;
         }
-        while delta_time >= step_time {
-            if !self.step(step_time.as_secs_f32()) {
+        // Scaling the real time a step takes instead of the step time keeps the simulation the same at any speed.
+        let mut real_step_time = step_time.div_f32(self.state.time_scale);
+        while delta_time >= real_step_time {
+            self.last_update += real_step_time;
+            if !self.step(step_time.as_secs_f32())? {
                 return Ok(false);
             }
-            delta_time -= step_time;
+            delta_time -= real_step_time;
+            real_step_time = step_time.div_f32(self.state.time_scale);
         }
 
-        self.debug();
-        self.renderer.render(&mut self.state)?;
+        // The remaining time is simulated on the next update, but rendering interpolates into it.
+        if let Some(renderer) = &mut self.renderer {
+            let interpolation = (delta_time.as_secs_f32() / real_step_time.as_secs_f32()).min(1.0);
+            Self::debug(&mut self.state, &mut self.physics);
+            renderer.render(&mut self.state, &self.settings, interpolation)
+                .map_err(|error| GameError::new(ErrorKind::Other, "rendering", error))?;
+            Self::end_profile_frame(&mut self.state.debug.profile);
+        }
         Ok(true)
     }
 
 
-    fn step(&mut self, delta_time: f32) -> bool {
-        // TODO: Fix event timing.
-        while let Ok(event) = self.event_receiver.try_recv() {
-            if !self.handle_event(event) {
-                return false;
+    /// Reloads the level, shaders and textures that changed on disk, and shows why if that fails.
+    fn hot_reload(&mut self) {
+        let changed = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return
+        };
+        for file in changed {
+            let result = if file == Self::level_file(&self.config, self.campaign.as_ref(), self.level_index) {
+                self.reload_level(&file)
+            }
+            else if let Some(renderer) = &mut self.renderer {
+                renderer.reload(&file)
+            }
+            else {
+                Ok(())
+            };
+            match result {
+                Ok(()) => {
+                    info!("reloaded {}", file.display());
+                    self.state.debug.reload_error = None;
+                }
+                Err(error) => {
+                    warn!("{}", error);
+                    self.state.debug.reload_error = Some(error.to_string());
+                }
+            }
+        }
+    }
+
+
+    /// Replaces the ground with the one from the changed level file, leaving the cat where it is.
+    fn reload_level(&mut self, file: &Path) -> Result<(), GameError> {
+        let context = format!("reloading the level {}", file.display());
+        let level = Level::load(file).map_err(|error| GameError::new(ErrorKind::Level, &context, error))?;
+        // The new ground is rebuilt by physics and the renderer through its dirty flags.
+        self.state.ground = Self::level_ground(&level);
+        self.state.editor.spawn = level.spawn;
+        self.level = level;
+        // Rewinding would restore links into boxes that may no longer be there.
+        self.history.clear();
+        Ok(())
+    }
+
+
+    /// Runs a fixed number of steps without looking at the clock.
+    pub fn simulate(&mut self, steps: u64) -> Result<bool, GameError> {
+        let step_time = self.step_time;
+        for _ in 0..steps {
+            self.last_update += step_time;
+            if !self.step(step_time.as_secs_f32())? {
+                return Ok(false);
             }
         }
+        Ok(true)
+    }
+
+
+    fn step(&mut self, delta_time: f32) -> Result<bool, GameError> {
+        let start = time::Instant::now();
+        if !self.handle_events().map_err(|error| GameError::new(ErrorKind::Other, "recording input", error))? {
+            return Ok(false);
+        }
+        self.state.debug.profile.current[state::ProfileSection::Events as usize] += start.elapsed();
+        self.step_index += 1;
+
+        let cat = &mut self.state.cat;
+        cat.previous_path.clone_from(&cat.path);
+        cat.previous_tail.clone_from(&cat.tail);
+        for prop in self.state.props.iter_mut() {
+            prop.previous_position = prop.position;
+            prop.previous_angle = prop.angle;
+        }
 
         self.update_debug();
+        self.update_settings();
+
+        match scene::active(&self.state) {
+            Scene::Gameplay => self.step_gameplay(delta_time),
+            _ => self.step_menu()
+        }
+    }
+
+
+    fn step_menu(&mut self) -> Result<bool, GameError> {
+        let item = match scene::update_menu(&mut self.state) {
+            Some(item) => item,
+            None => return Ok(true)
+        };
+        match item {
+            scene::Item::Start => {
+                self.play_level(0)?;
+                scene::set(&mut self.state, Scene::Gameplay);
+            }
+            scene::Item::Continue => {
+                let levels = self.campaign.as_ref().map_or(1, |campaign| campaign.levels.len());
+                let unlocked = self.progress.as_ref().map_or(1, |progress| progress.unlocked);
+                self.play_level(unlocked.min(levels).max(1) - 1)?;
+                scene::set(&mut self.state, Scene::Gameplay);
+            }
+            scene::Item::Restart => {
+                self.restart()?;
+                scene::set(&mut self.state, Scene::Gameplay);
+            }
+            scene::Item::NextLevel => {
+                let levels = self.campaign.as_ref().map_or(1, |campaign| campaign.levels.len());
+                if self.level_index + 1 < levels {
+                    self.play_level(self.level_index + 1)?;
+                    scene::set(&mut self.state, Scene::Gameplay);
+                }
+                else {
+                    info!("completed the last level");
+                    scene::set(&mut self.state, Scene::Title);
+                }
+            }
+            scene::Item::Resume => scene::pop(&mut self.state),
+            scene::Item::Title => scene::set(&mut self.state, Scene::Title),
+            scene::Item::Quit => return Ok(false)
+        }
+        Ok(true)
+    }
+
+
+    fn step_gameplay(&mut self, delta_time: f32) -> Result<bool, GameError> {
+        let input = &mut self.state.input;
+        input.confirm = false;
+        input.menu_up = false;
+        input.menu_down = false;
+        if input.menu {
+            scene::push(&mut self.state, Scene::PauseMenu);
+            return Ok(true);
+        }
+
         self.update_gui();
+        if self.state.input.toggle_editor {
+            self.state.input.toggle_editor = false;
+            self.toggle_editor();
+        }
+        if self.state.editor.open {
+            self.update_editor(delta_time);
+            return Ok(true);
+        }
+        self.update_snapshot();
 
         if self.state.input.toggle_pause {
             self.state.input.toggle_pause = false;
             self.state.paused ^= true;
         }
+        // Rewinding works while paused, so that a paused moment can be scrubbed backwards. Stepping forwards again
+        // simulates new steps, since the rewound ones are dropped from the history.
+        self.state.rewinding = self.state.input.rewind;
+        if self.state.rewinding {
+            if let Some(snapshot) = self.history.pop_back() {
+                snapshot.restore(&mut self.state, &mut self.physics);
+            }
+            return Ok(true);
+        }
         if self.state.paused && !self.state.input.step {
-            return true;
+            return Ok(true);
         }
         self.state.input.step = false;
 
-        self.update_cat();
+        if self.history.len() >= self.history_steps {
+            self.history.pop_front();
+        }
+        let snapshot = snapshot::Snapshot::new(&self.state, &self.physics, self.history.back());
+        self.history.push_back(snapshot);
+
+        self.update_drag().map_err(|error| GameError::new(ErrorKind::Other, "recording input", error))?;
+
+        let start = time::Instant::now();
+        self.update_cat(delta_time);
+        self.state.debug.profile.current[state::ProfileSection::UpdateCat as usize] += start.elapsed();
 
         self.physics.step(&mut self.state, delta_time);
-        true
+        self.level_steps += 1;
+
+        // Turning teleports the links, so it shouldn't be interpolated.
+        let cat = &mut self.state.cat;
+        if cat.turning {
+            cat.previous_path.clone_from(&cat.path);
+            cat.previous_tail.clone_from(&cat.tail);
+        }
+
+        self.update_goals();
+        Ok(true)
     }
 
 
-    fn debug(&mut self) {
-        let debug = &mut self.state.debug;
+    /// Feeds the cat, and ends the level when the cat reaches the exit, touches spikes or falls out of the level.
+    fn update_goals(&mut self) {
+        let eaten = self.physics.touched_food();
+        if !eaten.is_empty() {
+            let ground = &mut self.state.ground;
+            ground.food.retain(|p| !eaten.contains(p));
+            ground.dirty = state::DirtyFlags::all();
+            self.state.cat.energy += FOOD_ENERGY * eaten.len() as u32;
+        }
+
+        let head = self.state.cat.path.back().copied().unwrap();
+        let ground = &self.state.ground;
+        if ground.exit.is_some_and(|exit| (head - exit).length() < 0.75) {
+            self.complete_level();
+            self.update_gui();
+            scene::push(&mut self.state, Scene::LevelComplete);
+            return;
+        }
+        if self.physics.touches_spikes() {
+            scene::push(&mut self.state, Scene::GameOver);
+            return;
+        }
+        // The level ends at its outermost tiles, whatever they are, since a level can be bordered by water or spikes.
+        let tiles = ground.boxes.iter()
+            .chain(ground.tiles.iter().map(|(p, _)| p))
+            .chain(ground.food.iter())
+            .chain(ground.exit.iter());
+        let (min, max) = tiles.fold(
+            (head, head),
+            |(min, max), p| (vec2(min.x.min(p.x), min.y.min(p.y)), vec2(max.x.max(p.x), max.y.max(p.y)))
+        );
+        if head.x <= min.x || head.x >= max.x || head.y <= min.y || head.y >= max.y {
+            scene::push(&mut self.state, Scene::GameOver);
+        }
+    }
+
+
+    fn handle_events(&mut self) -> Result<bool, Box<dyn Error>> {
+        if let Some(player) = &mut self.player {
+            let mut events = Vec::new();
+            while let Some(event) = player.next_event(self.step_index) {
+                events.push(event);
+            }
+            if player.is_finished() {
+                self.player = None;
+            }
+            for event in events {
+                self.handle_event(event);
+            }
+            // Only window events are taken from the event receiver during playback.
+            while let Ok(event) = self.event_receiver.try_recv() {
+                match event {
+                    Event::Close => return Ok(self.handle_event(event)),
+                    Event::Resize(..) | Event::Fullscreen(..) | Event::WindowSize(..) | Event::CursorLeft => {
+                        self.handle_event(event);
+                    }
+                    _ => {}
+                }
+            }
+            return Ok(true);
+        }
+
+        while let Ok(event) = self.event_receiver.try_recv() {
+            self.pending_events.push_back(event);
+        }
+
+        // Apply the events that happened before the end of this step.
+        let mut pressed = Vec::new();
+        while let Some(event) = self.pending_events.front() {
+            if event.time().is_some_and(|time| time > self.last_update) {
+                break;
+            }
+            if let Some((action, button)) = event.button() {
+                // Delay releasing a button that was pressed on this step, so that short taps aren't lost.
+                if action == glfw::Action::Release && pressed.contains(&button) {
+                    break;
+                }
+                if action == glfw::Action::Press {
+                    pressed.push(button);
+                }
+            }
+            let event = self.pending_events.pop_front().unwrap();
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(self.step_index, &event)?;
+            }
+            if !self.handle_event(event) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-        debug.paused = self.state.paused;
+
+    fn debug(state: &mut State, physics: &mut physics::World) {
+        let debug = &mut state.debug;
+
+        debug.paused = state.paused;
+        debug.rewinding = state.rewinding;
+        debug.time_scale = state.time_scale;
 
         if debug.show_physics {
-            self.physics.debug(debug);
+            physics.debug(debug);
         }
 
         while let Some(time) = debug.frames.front() {
@@ -206,118 +776,410 @@ impl Game {
     }
 
 
+    fn end_profile_frame(profile: &mut state::Profile) {
+        let mut frame = [0.0; state::PROFILE_SECTIONS];
+        for (seconds, duration) in frame.iter_mut().zip(profile.current.iter_mut()) {
+            *seconds = duration.as_secs_f32();
+            *duration = time::Duration::from_secs(0);
+        }
+        if profile.frames.len() >= state::PROFILE_FRAMES {
+            profile.frames.pop_front();
+        }
+        profile.frames.push_back(frame);
+    }
+
+
     fn handle_event(&mut self, event: Event) -> bool {
-        let input = &mut self.state.input;
         match event {
             Event::Close => {
-                println!("close");
+                info!("close");
                 return false;
             }
-            Event::Key(action, glfw::Key::Left) => {
-                input.left = action != glfw::Action::Release;
+            Event::Key(action, key, _) => {
+                let actions = self.settings.controls.actions(key);
+                // Releases still reach the controls, so that keys held when the console opens don't get stuck.
+                if self.state.console.open && action != glfw::Action::Release {
+                    if actions.contains(&Action::ToggleConsole) {
+                        self.state.console.open = false;
+                    }
+                    else if let Some(command) = console::press_key(&mut self.state.console, key) {
+                        self.run_command(&command);
+                    }
+                    return true;
+                }
+                // Repeated key presses are only for typing.
+                if action == glfw::Action::Repeat {
+                    return true;
+                }
+                if actions.is_empty() {
+                    debug!("unbound key {:?}: {:?}", action, key);
+                }
+                for control in actions.iter().copied() {
+                    control.apply(&mut self.state.input, action != glfw::Action::Release);
+                }
+                if self.state.input.toggle_console {
+                    self.state.input.toggle_console = false;
+                    console::open(&mut self.state.console);
+                }
             }
-            Event::Key(action, glfw::Key::Right) => {
-                input.right = action != glfw::Action::Release;
+            Event::Char(c, _) => {
+                if self.state.console.open {
+                    console::type_char(&mut self.state.console, c);
+                }
             }
-            Event::Key(action, glfw::Key::Up) => {
-                input.up = action != glfw::Action::Release;
+            Event::MouseButton(action, glfw::MouseButton::Button1, _) => {
+                let input = &mut self.state.input;
+                input.drag = action != glfw::Action::Release;
+                input.start_drag = action == glfw::Action::Press;
             }
-            Event::Key(action, glfw::Key::Down) => {
-                input.down = action != glfw::Action::Release;
+            Event::MouseButton(action, glfw::MouseButton::Button2, _) => {
+                self.state.input.erase = action != glfw::Action::Release;
             }
-            Event::Key(action, glfw::Key::Z) => {
-                input.turn = action != glfw::Action::Release;
+            Event::MouseButton(..) => {}
+            Event::CursorPos(p, _) => self.state.input.cursor = Some(p),
+            Event::CursorLeft => self.state.input.cursor = None,
+            Event::GamepadButton(action, button, _) => {
+                for control in self.settings.controls.gamepad_actions(button).iter().copied() {
+                    control.apply(&mut self.state.input, action != glfw::Action::Release);
+                }
             }
-            Event::Key(action, glfw::Key::X) => {
-                input.extend = action != glfw::Action::Release;
+            Event::GamepadStick(stick, _) => {
+                self.state.input.stick = if stick.length() > STICK_DEAD_ZONE { Some(stick) } else { None };
             }
-            Event::Key(action, glfw::Key::C) => {
-                input.contract = action != glfw::Action::Release;
+            Event::Resize(width, height) => {
+                // A minimized window has an empty framebuffer.
+                if width > 0 && height > 0 {
+                    self.state.screen.width = width;
+                    self.state.screen.height = height;
+                }
             }
-            Event::Key(action, glfw::Key::LeftControl) => {
-                input.fly = action != glfw::Action::Release;
+            Event::Fullscreen(fullscreen) => self.settings.fullscreen = fullscreen,
+            Event::WindowSize(width, height) => self.settings.window_size = (width, height)
+        }
+        true
+    }
+
+
+    /// Drags the body under the cursor with the left mouse button, or as recorded when playing back a replay.
+    fn update_drag(&mut self) -> Result<(), Box<dyn Error>> {
+        let drag = match &mut self.player {
+            Some(player) => player.next_drag(self.step_index),
+            None => self.mouse_drag()
+        };
+        let drag = match drag {
+            Some(drag) => drag,
+            None => return Ok(())
+        };
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_drag(self.step_index, drag)?;
+        }
+        match drag {
+            replay::Drag::Start(p) => self.physics.start_drag(p),
+            replay::Drag::Move(p) => self.physics.drag_to(p),
+            replay::Drag::End => self.physics.end_drag()
+        }
+        Ok(())
+    }
+
+
+    fn mouse_drag(&mut self) -> Option<replay::Drag> {
+        let input = &mut self.state.input;
+        let cursor = match input.cursor {
+            Some(cursor) if input.drag => cursor,
+            _ => {
+                input.start_drag = false;
+                let was_dragging = self.dragging;
+                self.dragging = false;
+                return if was_dragging { Some(replay::Drag::End) } else { None };
+            }
+        };
+        let p = Renderer::screen_to_world(&self.state.screen, &self.state.camera, cursor);
+        self.dragging = true;
+        if input.start_drag {
+            input.start_drag = false;
+            Some(replay::Drag::Start(p))
+        }
+        else {
+            Some(replay::Drag::Move(p))
+        }
+    }
+
+
+    /// Opens the editor where the camera is, or closes it to carry on playing the edited level.
+    fn toggle_editor(&mut self) {
+        let editor = &mut self.state.editor;
+        editor.open ^= true;
+        if editor.open {
+            editor.camera = self.state.camera.position;
+            self.physics.end_drag();
+            info!("opened the editor");
+        }
+        else {
+            info!("closed the editor");
+        }
+    }
+
+
+    /// Moves the editor's camera, chooses the glyph to place and edits the tile under the cursor.
+    fn update_editor(&mut self, delta_time: f32) {
+        let input = &mut self.state.input;
+        let editor = &mut self.state.editor;
+        // Gameplay keys pressed in the editor shouldn't take effect when it's closed.
+        input.toggle_pause = false;
+        input.step = false;
+        input.turn = false;
+        let pan = vec2(
+            input.right as i8 as f32 - input.left as i8 as f32,
+            input.up as i8 as f32 - input.down as i8 as f32
+        );
+        editor.camera += pan * EDITOR_PAN_SPEED * delta_time;
+
+        if input.next_glyph || input.previous_glyph {
+            let glyphs = self.level.glyphs();
+            let i = glyphs.iter().position(|glyph| *glyph == editor.glyph).unwrap_or(0);
+            let i = if input.next_glyph { i + 1 } else { i + glyphs.len() - 1 };
+            input.next_glyph = false;
+            input.previous_glyph = false;
+            editor.glyph = glyphs[i % glyphs.len()];
+            editor.glyph_name = level::glyph_name(editor.glyph);
+        }
+
+        // The level starts at the origin, and tiles above or left of it can't be written to the level file.
+        let (screen, camera) = (&self.state.screen, &self.state.camera);
+        editor.cursor = input.cursor
+            .map(|cursor| Renderer::screen_to_world(screen, camera, cursor))
+            .map(|p| vec2(p.x.round(), p.y.round()))
+            .filter(|p| p.x >= 0.0 && p.y <= 0.0);
+        input.start_drag = false;
+        let props = self.level.props.clone();
+        let changed = match editor.cursor {
+            Some(p) if input.drag => self.level.set(p, editor.glyph),
+            Some(p) if input.erase => self.level.erase(p),
+            _ => false
+        };
+
+        if input.save_level {
+            input.save_level = false;
+            let file = Self::level_file(&self.config, self.campaign.as_ref(), self.level_index);
+            match fs::write(&file, self.level.write()) {
+                Ok(()) => info!("saved the level to {}", file.display()),
+                Err(error) => warn!("failed to save the level to {}: {}", file.display(), error)
             }
-            Event::Key(action, glfw::Key::P) => {
-                input.toggle_pause = action != glfw::Action::Release;
+        }
+
+        if changed {
+            self.state.editor.spawn = self.level.spawn;
+            // The ground is rebuilt by physics and the renderer through its dirty flags, but props are only created
+            // along with the physics world.
+            self.state.ground = Self::level_ground(&self.level);
+            if self.level.props != props {
+                self.physics = physics::World::new(&mut self.state);
             }
-            Event::Key(action, glfw::Key::O) => {
-                input.step = action != glfw::Action::Release;
+            // Rewinding would restore the level from before the edit.
+            self.history.clear();
+        }
+    }
+
+
+    fn run_command(&mut self, line: &str) {
+        info!("console: {}", line);
+        let command = match console::parse(line) {
+            Ok(command) => command,
+            Err(error) => {
+                console::print(&mut self.state.console, &error.to_string());
+                return;
             }
-            Event::Key(action, glfw::Key::Num0) => {
-                input.toggle_debug_physics = action != glfw::Action::Release;
+        };
+        let output = match command {
+            Command::Help => Some(console::HELP.to_string()),
+            Command::Clear => {
+                self.state.console.lines.clear();
+                None
             }
-            Event::Key(action, glfw::Key::Num1) => {
-                input.toggle_debug_physics_shapes = action != glfw::Action::Release;
+            Command::Energy(energy) => {
+                self.state.cat.energy = energy;
+                None
             }
-            Event::Key(action, glfw::Key::Num2) => {
-                input.toggle_debug_physics_joints = action != glfw::Action::Release;
+            Command::Teleport(p) => {
+                self.physics.teleport(&mut self.state, p);
+                // There is nothing to interpolate from.
+                let cat = &mut self.state.cat;
+                cat.previous_path.clone_from(&cat.path);
+                cat.previous_tail.clone_from(&cat.tail);
+                None
             }
-            Event::Key(action, glfw::Key::Num3) => {
-                input.toggle_debug_physics_aabbs = action != glfw::Action::Release;
+            Command::Spawn(prop, p) => match self.level.prop_def(&prop) {
+                Some(def) => {
+                    self.physics.spawn_prop(&mut self.state.props, p, def);
+                    None
+                }
+                None => Some(format!("the level has no prop '{}'", prop))
+            },
+            Command::Gravity(gravity) => {
+                self.physics.set_gravity(gravity);
+                None
             }
-            Event::Key(action, glfw::Key::Num4) => {
-                input.toggle_debug_physics_transforms = action != glfw::Action::Release;
+            Command::TimeScale(time_scale) => {
+                self.state.time_scale = time_scale;
+                None
             }
-            Event::Key(action, glfw::Key::Num5) => {
-                input.toggle_debug_physics_contacts = action != glfw::Action::Release;
+            Command::Debug(target, on) => {
+                let debug = &mut self.state.debug;
+                match target {
+                    DebugTarget::Physics => debug.show_physics = on,
+                    DebugTarget::PhysicsFlag(flag) => {
+                        debug.physics_flags.set(flag, on);
+                        self.settings.debug_physics = debug.physics_flags;
+                    }
+                    DebugTarget::Profiler => debug.show_profiler = on
+                }
+                None
             }
-            Event::Key(action, key) => {
-                println!("key {:?}: {:?}", action, key);
+            Command::Get(name) => Some(match self.settings.get(&name) {
+                Some(value) => format!("{} = {}", name, value),
+                None => format!("unknown setting '{}'", name)
+            }),
+            // The main thread owns the window and sends these as the window changes, so setting them here would only
+            // make them disagree with the window.
+            Command::Set(name, _) if name == "window" || name == "window_size" => {
+                Some(format!("{} follows the window, press F11 or resize the window instead", name))
             }
+            Command::Set(name, value) => match self.settings.set(&name, &value) {
+                Ok(()) => {
+                    self.state.debug.physics_flags = self.settings.debug_physics;
+                    self.state.steering = self.settings.steering;
+                    None
+                }
+                Err(error) => Some(error.to_string())
+            },
+            Command::Log(filter) => lib::log::set_filter(&filter).err().map(|error| error.to_string())
+        };
+        if let Some(output) = output {
+            console::print(&mut self.state.console, &output);
         }
-        true
     }
 
 
     fn update_debug(&mut self) {
         let input = &mut self.state.input;
         let debug = &mut self.state.debug;
-        if input.toggle_debug_physics {
-            input.toggle_debug_physics = false;
-            debug.show_physics ^= true;
+        if input.toggle_profiler {
+            input.toggle_profiler = false;
+            debug.show_profiler ^= true;
+        }
+        // The console can set scales between the steps, which go to the next step in either direction.
+        let time_scale = self.state.time_scale;
+        if input.slow_down {
+            input.slow_down = false;
+            let slower = state::TIME_SCALES.iter().rev().find(|scale| **scale < time_scale);
+            self.state.time_scale = slower.copied().unwrap_or(state::TIME_SCALES[0]);
+        }
+        if input.speed_up {
+            input.speed_up = false;
+            let faster = state::TIME_SCALES.iter().find(|scale| **scale > time_scale);
+            self.state.time_scale = faster.copied().unwrap_or(state::TIME_SCALES[state::TIME_SCALES.len() - 1]);
         }
-        if input.toggle_debug_physics_shapes {
-            input.toggle_debug_physics_shapes = false;
-            debug.physics_flags ^= state::DebugPhysics::SHAPES;
+    }
+
+
+    fn update_settings(&mut self) {
+        let input = &mut self.state.input;
+        let settings = &mut self.settings;
+        if input.toggle_vsync {
+            input.toggle_vsync = false;
+            settings.vsync ^= true;
         }
-        if input.toggle_debug_physics_joints {
-            input.toggle_debug_physics_joints = false;
-            debug.physics_flags ^= state::DebugPhysics::JOINTS;
+        let (min_zoom, max_zoom) = settings::ZOOM_RANGE;
+        if input.zoom_in {
+            input.zoom_in = false;
+            settings.zoom = (settings.zoom * 1.25).min(max_zoom);
         }
-        if input.toggle_debug_physics_aabbs {
-            input.toggle_debug_physics_aabbs = false;
-            debug.physics_flags ^= state::DebugPhysics::AABBS;
+        if input.zoom_out {
+            input.zoom_out = false;
+            settings.zoom = (settings.zoom / 1.25).max(min_zoom);
         }
-        if input.toggle_debug_physics_transforms {
-            input.toggle_debug_physics_transforms = false;
-            debug.physics_flags ^= state::DebugPhysics::TRANSFORMS;
+    }
+
+
+    fn update_snapshot(&mut self) {
+        let input = &mut self.state.input;
+        if input.save_snapshot {
+            input.save_snapshot = false;
+            match snapshot::save(&self.state, &self.physics) {
+                Ok(()) => info!("saved snapshot"),
+                Err(error) => warn!("failed to save snapshot: {}", error)
+            }
         }
-        if input.toggle_debug_physics_contacts {
-            input.toggle_debug_physics_contacts = false;
-            debug.physics_flags ^= state::DebugPhysics::CONTACTS;
+        else if input.load_snapshot {
+            input.load_snapshot = false;
+            match snapshot::load(&mut self.state, &mut self.physics) {
+                Ok(()) => {
+                    // The history may not fit the loaded ground.
+                    self.history.clear();
+                    info!("loaded snapshot");
+                }
+                Err(error) => warn!("failed to load snapshot: {}", error)
+            }
         }
     }
 
 
     fn update_gui(&mut self) {
-        self.state.gui.cat_energy = self.state.cat.energy;
+        let gui = &mut self.state.gui;
+        gui.cat_energy = self.state.cat.energy;
+        gui.level_time = self.level_steps as f32 * self.step_time.as_secs_f32();
+        gui.best_time = match (&self.campaign, &self.progress) {
+            (Some(campaign), Some(progress)) => progress.best_time(&campaign.levels[self.level_index]),
+            _ => None
+        };
     }
 
 
-    fn update_cat(&mut self) {
+    fn update_cat(&mut self, delta_time: f32) {
         let input = &mut self.state.input;
+        let steering = &self.state.steering;
         let cat = &mut self.state.cat;
 
-        // TODO: Smooth input.
-        if input.left ^ input.right || input.up ^ input.down {
+        // Find the requested heading and speed.
+        let target = if let Some(stick) = input.stick {
+            let speed = (stick.length() - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE);
+            Some((stick.to_angle(), speed.min(1.0)))
+        }
+        else if input.left ^ input.right || input.up ^ input.down {
             let d = vec2(
                 input.right as i8 as f32 - input.left as i8 as f32,
                 input.up as i8 as f32 - input.down as i8 as f32
             );
-            cat.direction = Some(d.to_angle());
+            Some((d.to_angle(), 1.0))
         }
         else {
-            cat.direction = None;
+            None
+        };
+
+        // Turn towards the heading and ramp the speed up or down.
+        match target {
+            Some((heading, speed)) => {
+                let direction = match cat.direction {
+                    Some(direction) => {
+                        let max_turn = steering.turn_rate * delta_time;
+                        direction + wrap_angle(heading - direction).max(-max_turn).min(max_turn)
+                    }
+                    None => heading
+                };
+                cat.direction = Some(wrap_angle(direction));
+                if cat.speed < speed {
+                    cat.speed = (cat.speed + steering.acceleration * delta_time).min(speed);
+                }
+                else {
+                    cat.speed = (cat.speed - steering.deceleration * delta_time).max(speed);
+                }
+            }
+            None => {
+                cat.speed = (cat.speed - steering.deceleration * delta_time).max(0.0);
+                if cat.speed <= 0.0 {
+                    cat.direction = None;
+                }
+            }
         }
 
         cat.turning = input.turn;
@@ -329,3 +1191,24 @@ impl Game {
         cat.flying = input.fly;
     }
 }
+
+
+impl Event {
+    fn time(&self) -> Option<time::Instant> {
+        match self {
+            Event::Close | Event::Resize(..) | Event::Fullscreen(..) | Event::WindowSize(..) => None,
+            Event::CursorLeft => None,
+            Event::Key(_, _, time) | Event::GamepadButton(_, _, time) | Event::GamepadStick(_, time) => Some(*time),
+            Event::Char(_, time) | Event::MouseButton(_, _, time) | Event::CursorPos(_, time) => Some(*time)
+        }
+    }
+
+
+    fn button(&self) -> Option<(glfw::Action, Button)> {
+        match self {
+            Event::Key(action, key, _) => Some((*action, Button::Key(*key))),
+            Event::GamepadButton(action, button, _) => Some((*action, Button::Gamepad(*button))),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    io::{self, Write},
+    env,
+    path::{Path, PathBuf}
+};
+
+
+pub struct CampaignError {
+    error: String
+}
+
+/// The levels of a campaign directory, in the order they are played.
+///
+/// The directory has a `manifest.txt` that lists one level file per line, relative to the directory. Lines starting
+/// with `#` are comments.
+pub struct Campaign {
+    dir: PathBuf,
+    pub levels: Vec<String>
+}
+
+/// How far the player got in the campaign, saved to the progress file whenever a level is completed.
+///
+/// The progress file has an `unlocked = N` line with how many levels can be played, and a `best LEVEL = SECONDS`
+/// line with the best time of each completed level.
+pub struct Progress {
+    pub unlocked: usize,
+    /// The best times of the completed levels, by the name in the manifest.
+    pub best_times: Vec<(String, f32)>
+}
+
+
+impl Error for CampaignError {}
+
+
+impl fmt::Debug for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Campaign {
+    pub fn load(dir: &Path) -> Result<Campaign, Box<dyn Error>> {
+        let manifest = dir.join("manifest.txt");
+        let source = fs::read_to_string(&manifest)
+            .map_err(|error| CampaignError { error: format!("{}: {}", manifest.display(), error) })?;
+        let levels: Vec<_> = source.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        if levels.is_empty() {
+            return Err(CampaignError { error: format!("{} lists no levels", manifest.display()) }.into());
+        }
+        Ok(Campaign { dir: dir.to_path_buf(), levels })
+    }
+
+
+    pub fn level_file(&self, index: usize) -> PathBuf {
+        self.dir.join(&self.levels[index])
+    }
+}
+
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress {
+            unlocked: 1,
+            best_times: Vec::new()
+        }
+    }
+}
+
+
+impl Progress {
+    /// Loads the progress file next to the executable, or no progress if there is none.
+    pub fn load() -> Result<Progress, Box<dyn Error>> {
+        let file = Self::file()?;
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(error.into())
+        };
+        let mut progress = Self::default();
+        for (n, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |error: &str| CampaignError { error: format!("{}:{}: {}", file.display(), n + 1, error) };
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = parts.next().ok_or_else(|| error("expected '='"))?.trim();
+            if name == "unlocked" {
+                progress.unlocked = value.parse().map_err(|_| error("unlocked expects a number"))?;
+            }
+            else if let Some(level) = name.strip_prefix("best ") {
+                let seconds = value.parse().map_err(|_| error("best times are in seconds"))?;
+                progress.best_times.push((level.trim().to_string(), seconds));
+            }
+            else {
+                return Err(error(&format!("unknown progress '{}'", name)).into());
+            }
+        }
+        Ok(progress)
+    }
+
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        let mut file = io::BufWriter::new(fs::File::create(Self::file()?)?);
+        writeln!(file, "# Written by the game when a level is completed.")?;
+        writeln!(file, "unlocked = {}", self.unlocked)?;
+        for (level, seconds) in &self.best_times {
+            writeln!(file, "best {} = {}", level, seconds)?;
+        }
+        file.flush()
+    }
+
+
+    fn file() -> Result<PathBuf, io::Error> {
+        Ok(env::current_exe()?.with_file_name("progress.txt"))
+    }
+
+
+    pub fn best_time(&self, level: &str) -> Option<f32> {
+        self.best_times.iter().find(|(other, _)| other == level).map(|(_, seconds)| *seconds)
+    }
+
+
+    /// Unlocks the level after the completed one and keeps the time if it's the best, and returns whether it was.
+    pub fn complete(&mut self, index: usize, level: &str, seconds: f32) -> bool {
+        self.unlocked = self.unlocked.max(index + 2);
+        match self.best_times.iter_mut().find(|(other, _)| other == level) {
+            Some((_, best)) if *best <= seconds => false,
+            Some((_, best)) => {
+                *best = seconds;
+                true
+            }
+            None => {
+                self.best_times.push((level.to_string(), seconds));
+                true
+            }
+        }
+    }
+}
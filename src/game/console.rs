@@ -0,0 +1,194 @@
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr
+};
+
+use glfw::Key;
+
+use lib::math::{Vec2, vec2};
+
+use super::state::{self, DebugPhysics};
+use super::settings;
+
+
+pub struct ConsoleError {
+    error: String
+}
+
+/// What the debug command turns on or off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugTarget {
+    Physics,
+    PhysicsFlag(DebugPhysics),
+    Profiler
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    Help,
+    Clear,
+    Energy(u32),
+    /// Moves the cat's head to a point.
+    Teleport(Vec2),
+    /// Spawns a prop at a point, by its glyph in the level or the name of a built-in one.
+    Spawn(String, Vec2),
+    Gravity(Vec2),
+    TimeScale(f32),
+    Debug(DebugTarget, bool),
+    Get(String),
+    Set(String, String),
+    Log(String)
+}
+
+
+pub const HELP: &str = "\
+energy N | teleport X Y | spawn PROP X Y | gravity X Y | timescale SCALE
+debug physics|shapes|joints|aabbs|transforms|contacts|profiler on|off
+get SETTING | set SETTING VALUE | log FILTER | clear | help";
+
+
+impl Error for ConsoleError {}
+
+
+impl fmt::Debug for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+/// Parses a command line like `teleport 10 -4`.
+pub fn parse(line: &str) -> Result<Command, ConsoleError> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let args: Vec<_> = words.collect();
+    let usage = |usage: &str| ConsoleError { error: format!("usage: {}", usage) };
+    let command = match (name, args.as_slice()) {
+        ("help", []) => Command::Help,
+        ("clear", []) => Command::Clear,
+        ("energy", [energy]) => Command::Energy(parse_arg(energy).ok_or_else(|| usage("energy N"))?),
+        ("teleport", [x, y]) => Command::Teleport(parse_vec2(x, y).ok_or_else(|| usage("teleport X Y"))?),
+        ("spawn", [prop, x, y]) => {
+            let p = parse_vec2(x, y).ok_or_else(|| usage("spawn PROP X Y"))?;
+            Command::Spawn(prop.to_string(), p)
+        }
+        ("gravity", [x, y]) => Command::Gravity(parse_vec2(x, y).ok_or_else(|| usage("gravity X Y"))?),
+        ("timescale", [scale]) => {
+            let (min, max) = state::TIME_SCALE_RANGE;
+            let scale = parse_arg(scale).filter(|scale| *scale >= min && *scale <= max)
+                .ok_or_else(|| ConsoleError { error: format!("time scale must be from {} to {}", min, max) })?;
+            Command::TimeScale(scale)
+        }
+        ("debug", [target, on]) => {
+            let target = match *target {
+                "physics" => DebugTarget::Physics,
+                "profiler" => DebugTarget::Profiler,
+                _ => settings::DEBUG_PHYSICS.iter()
+                    .find(|(_, name)| name == target)
+                    .map(|(flag, _)| DebugTarget::PhysicsFlag(*flag))
+                    .ok_or_else(|| ConsoleError { error: format!("unknown debug option '{}'", target) })?
+            };
+            let on = match *on {
+                "on" => true,
+                "off" => false,
+                _ => return Err(usage("debug OPTION on|off"))
+            };
+            Command::Debug(target, on)
+        }
+        ("get", [name]) => Command::Get(name.to_string()),
+        // Bindings have spaces after commas.
+        ("set", [name, ..]) if args.len() > 1 => Command::Set(name.to_string(), args[1..].join(" ")),
+        ("log", [filter]) => Command::Log(filter.to_string()),
+        ("energy", _) => return Err(usage("energy N")),
+        ("teleport", _) => return Err(usage("teleport X Y")),
+        ("spawn", _) => return Err(usage("spawn PROP X Y")),
+        ("gravity", _) => return Err(usage("gravity X Y")),
+        ("timescale", _) => return Err(usage("timescale SCALE")),
+        ("debug", _) => return Err(usage("debug OPTION on|off")),
+        ("get", _) => return Err(usage("get SETTING")),
+        ("set", _) => return Err(usage("set SETTING VALUE")),
+        ("log", _) => return Err(usage("log FILTER")),
+        _ => return Err(ConsoleError { error: format!("unknown command '{}', try help", name) })
+    };
+    Ok(command)
+}
+
+
+fn parse_arg<T: FromStr>(arg: &str) -> Option<T> {
+    arg.parse().ok()
+}
+
+
+fn parse_vec2(x: &str, y: &str) -> Option<Vec2> {
+    Some(vec2(parse_arg(x)?, parse_arg(y)?))
+}
+
+
+pub fn open(console: &mut state::Console) {
+    console.open = true;
+    console.skip_char = true;
+}
+
+
+/// Adds a line of output, dropping the oldest line if the console is full.
+pub fn print(console: &mut state::Console, line: &str) {
+    for line in line.lines() {
+        if console.lines.len() >= state::CONSOLE_LINES {
+            console.lines.pop_front();
+        }
+        console.lines.push_back(line.to_string());
+    }
+}
+
+
+pub fn type_char(console: &mut state::Console, c: char) {
+    if console.skip_char {
+        console.skip_char = false;
+        return;
+    }
+    // The font only has printable ASCII.
+    if c == ' ' || c.is_ascii_graphic() {
+        console.input.push(c);
+    }
+}
+
+
+/// Edits the command with a key press, and returns the command when it's entered.
+pub fn press_key(console: &mut state::Console, key: Key) -> Option<String> {
+    console.skip_char = false;
+    match key {
+        Key::Backspace => {
+            console.input.pop();
+        }
+        Key::Escape => console.open = false,
+        Key::Up if !console.commands.is_empty() => {
+            let i = console.recalled.map_or(console.commands.len() - 1, |i| i.saturating_sub(1));
+            console.recalled = Some(i);
+            console.input = console.commands[i].clone();
+        }
+        Key::Down => {
+            console.recalled = console.recalled.map(|i| i + 1).filter(|i| *i < console.commands.len());
+            console.input = console.recalled.map_or(String::new(), |i| console.commands[i].clone());
+        }
+        Key::Enter | Key::KpEnter => {
+            let command = std::mem::take(&mut console.input);
+            console.recalled = None;
+            if command.trim().is_empty() {
+                return None;
+            }
+            print(console, &format!("> {}", command));
+            console.commands.push(command.clone());
+            return Some(command);
+        }
+        _ => {}
+    }
+    None
+}
@@ -0,0 +1,307 @@
+use std::{
+    error::Error,
+    fmt,
+    collections::HashMap
+};
+
+use glfw::{Key, GamepadButton};
+
+use super::{state, key};
+
+
+pub struct ControlsError {
+    error: String
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Turn,
+    Extend,
+    Contract,
+    Fly,
+    TogglePause,
+    Step,
+    SaveSnapshot,
+    LoadSnapshot,
+    Rewind,
+    Menu,
+    Confirm,
+    MenuUp,
+    MenuDown,
+    ToggleProfiler,
+    ToggleVsync,
+    ZoomIn,
+    ZoomOut,
+    SlowDown,
+    SpeedUp,
+    ToggleConsole,
+    ToggleEditor,
+    SaveLevel,
+    NextGlyph,
+    PreviousGlyph
+}
+
+/// Maps keys and gamepad buttons to input actions.
+///
+/// Action names are the field names of `state::Input`, and key and button names are the names of `glfw::Key` and
+/// `glfw::GamepadButton`.
+pub struct Controls {
+    keys: HashMap<Key, Vec<Action>>,
+    buttons: HashMap<GamepadButton, Vec<Action>>
+}
+
+
+pub const ACTIONS: [(Action, &str); 28] = [
+    (Action::Left, "left"),
+    (Action::Right, "right"),
+    (Action::Up, "up"),
+    (Action::Down, "down"),
+    (Action::Turn, "turn"),
+    (Action::Extend, "extend"),
+    (Action::Contract, "contract"),
+    (Action::Fly, "fly"),
+    (Action::TogglePause, "toggle_pause"),
+    (Action::Step, "step"),
+    (Action::SaveSnapshot, "save_snapshot"),
+    (Action::LoadSnapshot, "load_snapshot"),
+    (Action::Rewind, "rewind"),
+    (Action::Menu, "menu"),
+    (Action::Confirm, "confirm"),
+    (Action::MenuUp, "menu_up"),
+    (Action::MenuDown, "menu_down"),
+    (Action::ToggleProfiler, "toggle_profiler"),
+    (Action::ToggleVsync, "toggle_vsync"),
+    (Action::ZoomIn, "zoom_in"),
+    (Action::ZoomOut, "zoom_out"),
+    (Action::SlowDown, "slow_down"),
+    (Action::SpeedUp, "speed_up"),
+    (Action::ToggleConsole, "toggle_console"),
+    (Action::ToggleEditor, "toggle_editor"),
+    (Action::SaveLevel, "save_level"),
+    (Action::NextGlyph, "next_glyph"),
+    (Action::PreviousGlyph, "previous_glyph")
+];
+
+
+impl Error for ControlsError {}
+
+
+impl fmt::Debug for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|(_, action_name)| *action_name == name).map(|(action, _)| *action)
+    }
+
+
+    pub fn apply(self, input: &mut state::Input, pressed: bool) {
+        match self {
+            Action::Left => input.left = pressed,
+            Action::Right => input.right = pressed,
+            Action::Up => input.up = pressed,
+            Action::Down => input.down = pressed,
+            Action::Turn => input.turn = pressed,
+            Action::Extend => input.extend = pressed,
+            Action::Contract => input.contract = pressed,
+            Action::Fly => input.fly = pressed,
+            Action::TogglePause => input.toggle_pause = pressed,
+            Action::Step => input.step = pressed,
+            Action::SaveSnapshot => input.save_snapshot = pressed,
+            Action::LoadSnapshot => input.load_snapshot = pressed,
+            Action::Rewind => input.rewind = pressed,
+            Action::Menu => input.menu = pressed,
+            Action::Confirm => input.confirm = pressed,
+            Action::MenuUp => input.menu_up = pressed,
+            Action::MenuDown => input.menu_down = pressed,
+            Action::ToggleProfiler => input.toggle_profiler = pressed,
+            Action::ToggleVsync => input.toggle_vsync = pressed,
+            Action::ZoomIn => input.zoom_in = pressed,
+            Action::ZoomOut => input.zoom_out = pressed,
+            Action::SlowDown => input.slow_down = pressed,
+            Action::SpeedUp => input.speed_up = pressed,
+            Action::ToggleConsole => input.toggle_console = pressed,
+            Action::ToggleEditor => input.toggle_editor = pressed,
+            Action::SaveLevel => input.save_level = pressed,
+            Action::NextGlyph => input.next_glyph = pressed,
+            Action::PreviousGlyph => input.previous_glyph = pressed
+        }
+    }
+}
+
+
+impl Controls {
+    pub fn new() -> Controls {
+        let mut controls = Controls {
+            keys: HashMap::new(),
+            buttons: HashMap::new()
+        };
+        for (key, action) in [
+            (Key::Left, Action::Left),
+            (Key::Right, Action::Right),
+            (Key::Up, Action::Up),
+            (Key::Down, Action::Down),
+            (Key::Z, Action::Turn),
+            (Key::X, Action::Extend),
+            (Key::C, Action::Contract),
+            (Key::LeftControl, Action::Fly),
+            (Key::P, Action::TogglePause),
+            (Key::O, Action::Step),
+            (Key::F5, Action::SaveSnapshot),
+            (Key::F9, Action::LoadSnapshot),
+            (Key::R, Action::Rewind),
+            (Key::Escape, Action::Menu),
+            (Key::Enter, Action::Confirm),
+            (Key::KpEnter, Action::Confirm),
+            (Key::Up, Action::MenuUp),
+            (Key::Down, Action::MenuDown),
+            (Key::F3, Action::ToggleProfiler),
+            (Key::F8, Action::ToggleVsync),
+            (Key::Equal, Action::ZoomIn),
+            (Key::KpAdd, Action::ZoomIn),
+            (Key::Minus, Action::ZoomOut),
+            (Key::KpSubtract, Action::ZoomOut),
+            (Key::LeftBracket, Action::SlowDown),
+            (Key::RightBracket, Action::SpeedUp),
+            (Key::GraveAccent, Action::ToggleConsole),
+            (Key::Tab, Action::ToggleEditor),
+            (Key::F6, Action::SaveLevel),
+            (Key::Period, Action::NextGlyph),
+            (Key::Comma, Action::PreviousGlyph)
+        ].iter().copied() {
+            controls.bind(key, action);
+        }
+        for (button, action) in [
+            (GamepadButton::ButtonDpadLeft, Action::Left),
+            (GamepadButton::ButtonDpadRight, Action::Right),
+            (GamepadButton::ButtonDpadUp, Action::Up),
+            (GamepadButton::ButtonDpadDown, Action::Down),
+            (GamepadButton::ButtonA, Action::Turn),
+            (GamepadButton::ButtonX, Action::Extend),
+            (GamepadButton::ButtonY, Action::Contract),
+            (GamepadButton::ButtonB, Action::Fly),
+            (GamepadButton::ButtonStart, Action::Menu),
+            (GamepadButton::ButtonLeftBumper, Action::Rewind),
+            (GamepadButton::ButtonA, Action::Confirm),
+            (GamepadButton::ButtonDpadUp, Action::MenuUp),
+            (GamepadButton::ButtonDpadDown, Action::MenuDown)
+        ].iter().copied() {
+            controls.bind_gamepad(button, action);
+        }
+        controls
+    }
+
+
+    /// Replaces the bindings of an action with a list of key and button names, e.g. `Z, Space, ButtonA`.
+    pub fn set_bindings(&mut self, action: Action, names: &str) -> Result<(), ControlsError> {
+        let mut keys = Vec::new();
+        let mut buttons = Vec::new();
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if let Some(key) = key::from_name(name) {
+                keys.push(key);
+            }
+            else if let Some(button) = key::gamepad_button_from_name(name) {
+                buttons.push(button);
+            }
+            else {
+                return Err(ControlsError { error: format!("unknown key '{}'", name) });
+            }
+        }
+        self.unbind_action(action);
+        for key in keys {
+            self.bind(key, action);
+        }
+        for button in buttons {
+            self.bind_gamepad(button, action);
+        }
+        Ok(())
+    }
+
+
+    /// The names of the keys and buttons bound to an action, in the format of `set_bindings`.
+    pub fn bindings(&self, action: Action) -> String {
+        let mut keys: Vec<_> = self.keys.iter()
+            .filter(|(_, actions)| actions.contains(&action))
+            .map(|(key, _)| format!("{:?}", key))
+            .collect();
+        let mut buttons: Vec<_> = self.buttons.iter()
+            .filter(|(_, actions)| actions.contains(&action))
+            .map(|(button, _)| format!("{:?}", button))
+            .collect();
+        // Hash map order changes from run to run.
+        keys.sort();
+        buttons.sort();
+        keys.extend(buttons);
+        keys.join(", ")
+    }
+
+
+    /// The keys bound to an action as shown in hints, e.g. `F3` or `` `, F1 ``, or `unbound` if there are none.
+    pub fn key_labels(&self, action: Action) -> String {
+        let mut keys: Vec<_> = self.keys.iter()
+            .filter(|(_, actions)| actions.contains(&action))
+            .map(|(key, _)| key::label(*key))
+            .collect();
+        if keys.is_empty() {
+            return "unbound".to_string();
+        }
+        keys.sort();
+        keys.join(", ")
+    }
+
+
+    pub fn bind(&mut self, key: Key, action: Action) {
+        let actions = self.keys.entry(key).or_default();
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+
+
+    pub fn bind_gamepad(&mut self, button: GamepadButton, action: Action) {
+        let actions = self.buttons.entry(button).or_default();
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+
+
+    pub fn unbind_action(&mut self, action: Action) {
+        for actions in self.keys.values_mut().chain(self.buttons.values_mut()) {
+            actions.retain(|a| *a != action);
+        }
+        self.keys.retain(|_, actions| !actions.is_empty());
+        self.buttons.retain(|_, actions| !actions.is_empty());
+    }
+
+
+    pub fn actions(&self, key: Key) -> &[Action] {
+        match self.keys.get(&key) {
+            Some(actions) => actions.as_slice(),
+            None => &[]
+        }
+    }
+
+
+    pub fn gamepad_actions(&self, button: GamepadButton) -> &[Action] {
+        match self.buttons.get(&button) {
+            Some(actions) => actions.as_slice(),
+            None => &[]
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    error::Error,
+    fmt
+};
+
+use lib::rgl;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    Asset,
+    Font,
+    GL,
+    Level,
+    Config,
+    Other
+}
+
+/// An error sorted into a category, along with what the game was doing when it happened.
+///
+/// The source error is kept as text, so that errors can be sent from the game thread to the main thread.
+pub struct GameError {
+    kind: ErrorKind,
+    context: String,
+    error: String
+}
+
+
+impl Error for GameError {}
+
+
+impl fmt::Debug for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} error while {}: {}", self.kind, self.context, self.error)
+    }
+}
+
+
+impl GameError {
+    /// GL errors are always sorted into `ErrorKind::GL`, since they mean that the GL context may be unusable.
+    pub fn new<E: Into<Box<dyn Error>>>(kind: ErrorKind, context: &str, error: E) -> GameError {
+        let error = error.into();
+        let kind = if error.is::<rgl::GLError>() { ErrorKind::GL } else { kind };
+        GameError {
+            kind,
+            context: context.to_string(),
+            error: error.to_string()
+        }
+    }
+
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+
+    /// Logs the error, which also writes it to the log file if there is one.
+    pub fn report(&self) {
+        error!("{}", self);
+    }
+}
@@ -0,0 +1,70 @@
+use glfw::{Action, Key, GamepadButton};
+
+
+const KEYS: [Key; 121] = [
+    Key::Space, Key::Apostrophe, Key::Comma, Key::Minus, Key::Period, Key::Slash,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4,
+    Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Semicolon, Key::Equal,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I,
+    Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R,
+    Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::LeftBracket, Key::Backslash, Key::RightBracket, Key::GraveAccent, Key::World1, Key::World2,
+    Key::Escape, Key::Enter, Key::Tab, Key::Backspace, Key::Insert, Key::Delete,
+    Key::Right, Key::Left, Key::Down, Key::Up,
+    Key::PageUp, Key::PageDown, Key::Home, Key::End,
+    Key::CapsLock, Key::ScrollLock, Key::NumLock, Key::PrintScreen, Key::Pause,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9,
+    Key::F10, Key::F11, Key::F12, Key::F13, Key::F14, Key::F15, Key::F16, Key::F17,
+    Key::F18, Key::F19, Key::F20, Key::F21, Key::F22, Key::F23, Key::F24, Key::F25,
+    Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4,
+    Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9,
+    Key::KpDecimal, Key::KpDivide, Key::KpMultiply, Key::KpSubtract, Key::KpAdd, Key::KpEnter, Key::KpEqual,
+    Key::LeftShift, Key::LeftControl, Key::LeftAlt, Key::LeftSuper,
+    Key::RightShift, Key::RightControl, Key::RightAlt, Key::RightSuper,
+    Key::Menu, Key::Unknown
+];
+
+
+/// Finds a key by the name of its variant, e.g. "LeftControl".
+pub fn from_name(name: &str) -> Option<Key> {
+    KEYS.iter().copied().find(|key| format!("{:?}", key) == name)
+}
+
+
+/// A short name for showing a key to the player, e.g. "`" for `GraveAccent`.
+pub fn label(key: Key) -> String {
+    let symbol = match key {
+        Key::Apostrophe => "'",
+        Key::Comma => ",",
+        Key::Minus => "-",
+        Key::Period => ".",
+        Key::Slash => "/",
+        Key::Semicolon => ";",
+        Key::Equal => "=",
+        Key::LeftBracket => "[",
+        Key::Backslash => "\\",
+        Key::RightBracket => "]",
+        Key::GraveAccent => "`",
+        _ => return format!("{:?}", key)
+    };
+    symbol.to_string()
+}
+
+
+pub fn action_from_name(name: &str) -> Option<Action> {
+    match name {
+        "Press" => Some(Action::Press),
+        "Release" => Some(Action::Release),
+        "Repeat" => Some(Action::Repeat),
+        _ => None
+    }
+}
+
+
+pub fn gamepad_button_from_name(name: &str) -> Option<GamepadButton> {
+    (0..).map(GamepadButton::from_i32)
+        .take_while(Option::is_some)
+        .flatten()
+        .find(|button| format!("{:?}", button) == name)
+}
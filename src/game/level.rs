@@ -0,0 +1,517 @@
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    fs,
+    str::FromStr,
+    path::Path
+};
+
+use lib::math::{Vec2, vec2};
+
+use super::state::{Tile, PropDef, PropShape, BodyType};
+
+
+/// An error in a level, with the line and column of the glyph that caused it, both counted from 1.
+pub struct LevelError {
+    pub position: Option<(usize, usize)>,
+    error: String
+}
+
+/// A parsed level file.
+///
+/// Each character of the file is a one unit tile, with the first line at the top and `y` pointing up, so the top left
+/// tile is at the origin. `X` is a ground box, `P` the player spawn, `E` the exit and `F` food. The glyphs of other
+/// tiles are listed in `TILES`. Spaces are empty, and other characters are ignored with a warning.
+///
+/// Props are placed with the glyphs of their definitions. A `[props]` line ends the tiles, and is followed by
+/// definitions that add to or replace the built-in ones in `PROPS`, e.g.
+///
+/// ```text
+/// [props]
+/// # glyph = body type, shape and size, then options
+/// o = dynamic circle 0.5 density=2
+/// r = kinematic box 4 1 angular_velocity=0.8 color=160,160,190
+/// ```
+///
+/// The options are `density`, `friction`, `velocity=X,Y`, `angular_velocity` and `color=R,G,B`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Level {
+    pub boxes: Vec<Vec2>,
+    pub tiles: Vec<(Vec2, Tile)>,
+    pub food: Vec<Vec2>,
+    pub props: Vec<(Vec2, PropDef)>,
+    /// The built-in prop definitions, and the ones added or replaced by the level.
+    pub prop_defs: Vec<(char, PropDef)>,
+    pub spawn: Vec2,
+    pub exit: Option<Vec2>
+}
+
+
+/// The glyphs of tiles, and their names in snapshot files.
+pub const TILES: [(Tile, char, &str); 5] = [
+    (Tile::OneWay, '-', "one_way"),
+    (Tile::Ice, 'I', "ice"),
+    (Tile::Sticky, 'S', "sticky"),
+    (Tile::Spikes, '^', "spikes"),
+    (Tile::Water, '~', "water")
+];
+
+/// The names of the built-in props that can be spawned without a glyph, even when a level replaces it.
+pub const PROP_NAMES: [(&str, char); 2] = [("circle", 'o'), ("box", 'b')];
+
+/// The glyphs other than `TILES` that can't be used for props.
+const RESERVED: [char; 5] = [' ', 'X', 'P', 'E', 'F'];
+
+/// The props that every level can use.
+pub const PROPS: [(char, PropDef); 3] = [
+    ('o', PropDef {
+        shape: PropShape::Circle(0.5),
+        body_type: BodyType::Dynamic,
+        density: 1.0,
+        friction: 0.2,
+        velocity: Vec2 { x: 0.0, y: 0.0 },
+        angular_velocity: 0.0,
+        color: (230, 180, 120)
+    }),
+    ('b', PropDef {
+        shape: PropShape::Box(1.0, 1.0),
+        body_type: BodyType::Dynamic,
+        density: 1.0,
+        friction: 0.2,
+        velocity: Vec2 { x: 0.0, y: 0.0 },
+        angular_velocity: 0.0,
+        color: (200, 160, 110)
+    }),
+    // A plank spinning around its center.
+    ('r', PropDef {
+        shape: PropShape::Box(4.0, 1.0),
+        body_type: BodyType::Kinematic,
+        density: 1.0,
+        friction: 0.2,
+        velocity: Vec2 { x: 0.0, y: 0.0 },
+        angular_velocity: std::f32::consts::PI * 0.25,
+        color: (160, 160, 190)
+    })
+];
+
+
+impl Error for LevelError {}
+
+
+impl fmt::Debug for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.error),
+            None => write!(f, "{}", self.error)
+        }
+    }
+}
+
+
+impl Level {
+    pub fn load(file: &Path) -> Result<Level, Box<dyn Error>> {
+        Ok(Self::parse(&fs::read_to_string(file)?)?)
+    }
+
+
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let lines: Vec<_> = source.lines().collect();
+        let tile_lines = lines.iter().position(|line| line.trim() == "[props]").unwrap_or(lines.len());
+
+        let mut prop_defs = PROPS.to_vec();
+        for (n, line) in lines.iter().enumerate().skip(tile_lines + 1) {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let (glyph, def) = parse_prop_def(line)
+                .map_err(|(column, error)| LevelError { position: Some((n + 1, column)), error })?;
+            prop_defs.retain(|(other, _)| *other != glyph);
+            prop_defs.push((glyph, def));
+        }
+
+        let mut boxes = Vec::new();
+        let mut tiles = Vec::new();
+        let mut food = Vec::new();
+        let mut props = Vec::new();
+        let mut spawn = None;
+        let mut exit = None;
+        for (n, line) in lines[..tile_lines].iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let position = (n + 1, column + 1);
+                let p = vec2(column as f32, -(n as f32));
+                let error = |error: String| LevelError { position: Some(position), error };
+                match c {
+                    ' ' => {}
+                    'X' => boxes.push(p),
+                    'P' => {
+                        if let Some((_, (line, column))) = spawn {
+                            let first = format!("the first is at line {}, column {}", line, column);
+                            return Err(error(format!("second player spawn, {}", first)));
+                        }
+                        spawn = Some((p, position));
+                    }
+                    'E' => {
+                        if let Some((_, (line, column))) = exit {
+                            let first = format!("the first is at line {}, column {}", line, column);
+                            return Err(error(format!("second exit, {}", first)));
+                        }
+                        exit = Some((p, position));
+                    }
+                    'F' => food.push(p),
+                    // Lines may end in \r\n.
+                    '\r' => {}
+                    _ => {
+                        if let Some((tile, _, _)) = TILES.iter().find(|(_, glyph, _)| *glyph == c) {
+                            tiles.push((p, *tile));
+                        }
+                        else if let Some((_, def)) = prop_defs.iter().find(|(glyph, _)| *glyph == c) {
+                            props.push((p, *def));
+                        }
+                        else {
+                            warn!("line {}, column {}: ignoring unknown glyph {:?}", position.0, position.1, c);
+                        }
+                    }
+                }
+            }
+        }
+        let (spawn, _) = spawn
+            .ok_or_else(|| LevelError { position: None, error: "the level has no player spawn".to_string() })?;
+        Ok(Level {
+            boxes,
+            tiles,
+            food,
+            props,
+            prop_defs,
+            spawn,
+            exit: exit.map(|(p, _)| p)
+        })
+    }
+
+
+    /// Writes the level in the format that `parse` reads. Comments in the prop definitions aren't kept.
+    pub fn write(&self) -> String {
+        let mut glyphs = vec![(self.spawn, 'P')];
+        glyphs.extend(self.exit.map(|p| (p, 'E')));
+        glyphs.extend(self.boxes.iter().map(|p| (*p, 'X')));
+        glyphs.extend(self.food.iter().map(|p| (*p, 'F')));
+        glyphs.extend(self.tiles.iter().map(|(p, tile)| (*p, tile_glyph(*tile))));
+        glyphs.extend(self.props.iter().map(|(p, def)| (*p, self.prop_glyph(def))));
+
+        let columns = glyphs.iter().map(|(p, _)| p.x as usize + 1).max().unwrap_or(0);
+        let lines = glyphs.iter().map(|(p, _)| (-p.y) as usize + 1).max().unwrap_or(0);
+        let mut grid = vec![vec![' '; columns]; lines];
+        for (p, glyph) in glyphs {
+            grid[(-p.y) as usize][p.x as usize] = glyph;
+        }
+        let mut source = String::new();
+        for line in grid {
+            let line: String = line.into_iter().collect();
+            writeln!(source, "{}", line.trim_end()).unwrap();
+        }
+
+        let defs: Vec<_> = self.prop_defs.iter().filter(|def| !PROPS.contains(def)).collect();
+        if !defs.is_empty() {
+            writeln!(source, "[props]").unwrap();
+            for (glyph, def) in defs {
+                writeln!(source, "{} = {}", glyph, write_prop_def(def)).unwrap();
+            }
+        }
+        source
+    }
+
+
+    /// The glyphs that can be placed in the level, in the order the editor cycles through them.
+    pub fn glyphs(&self) -> Vec<char> {
+        let mut glyphs = vec!['X'];
+        glyphs.extend(TILES.iter().map(|(_, glyph, _)| *glyph));
+        glyphs.extend(['F', 'P', 'E'].iter().copied());
+        glyphs.extend(self.prop_defs.iter().map(|(glyph, _)| *glyph));
+        glyphs
+    }
+
+
+    /// The definition of a prop by its glyph in the level, or by its name in `PROP_NAMES`.
+    pub fn prop_def(&self, prop: &str) -> Option<&PropDef> {
+        if let Some((_, glyph)) = PROP_NAMES.iter().find(|(name, _)| *name == prop) {
+            PROPS.iter().find(|(other, _)| other == glyph).map(|(_, def)| def)
+        }
+        else {
+            let mut chars = prop.chars();
+            match (chars.next(), chars.next()) {
+                (Some(glyph), None) => self.prop_defs.iter().find(|(other, _)| *other == glyph).map(|(_, def)| def),
+                _ => None
+            }
+        }
+    }
+
+
+    /// The glyph at a tile, or a space if it's empty.
+    pub fn glyph(&self, p: Vec2) -> char {
+        if self.spawn == p {
+            'P'
+        }
+        else if self.exit == Some(p) {
+            'E'
+        }
+        else if self.boxes.contains(&p) {
+            'X'
+        }
+        else if self.food.contains(&p) {
+            'F'
+        }
+        else if let Some((_, tile)) = self.tiles.iter().find(|(other, _)| *other == p) {
+            tile_glyph(*tile)
+        }
+        else if let Some((_, def)) = self.props.iter().find(|(other, _)| *other == p) {
+            self.prop_glyph(def)
+        }
+        else {
+            ' '
+        }
+    }
+
+
+    fn prop_glyph(&self, def: &PropDef) -> char {
+        self.prop_defs.iter().find(|(_, other)| other == def).map_or('?', |(glyph, _)| *glyph)
+    }
+
+
+    /// Replaces what is at a tile with one of the `glyphs`, and returns whether the level changed.
+    ///
+    /// The spawn can be moved, but not replaced or erased, since a level needs one.
+    pub fn set(&mut self, p: Vec2, glyph: char) -> bool {
+        if self.glyph(p) == glyph || self.spawn == p || !self.glyphs().contains(&glyph) {
+            return false;
+        }
+        self.erase(p);
+        match glyph {
+            'X' => self.boxes.push(p),
+            'P' => self.spawn = p,
+            'E' => self.exit = Some(p),
+            'F' => self.food.push(p),
+            _ => {
+                if let Some((tile, _, _)) = TILES.iter().find(|(_, other, _)| *other == glyph) {
+                    self.tiles.push((p, *tile));
+                }
+                else if let Some((_, def)) = self.prop_defs.iter().find(|(other, _)| *other == glyph) {
+                    self.props.push((p, *def));
+                }
+            }
+        }
+        true
+    }
+
+
+    /// Empties a tile other than the spawn, and returns whether the level changed.
+    pub fn erase(&mut self, p: Vec2) -> bool {
+        let glyph = self.glyph(p);
+        if glyph == ' ' || glyph == 'P' {
+            return false;
+        }
+        self.boxes.retain(|other| *other != p);
+        self.tiles.retain(|(other, _)| *other != p);
+        self.food.retain(|other| *other != p);
+        self.props.retain(|(other, _)| *other != p);
+        if self.exit == Some(p) {
+            self.exit = None;
+        }
+        true
+    }
+}
+
+
+/// What a glyph places, for showing it in the editor.
+pub fn glyph_name(glyph: char) -> &'static str {
+    match glyph {
+        'X' => "box",
+        'P' => "spawn",
+        'E' => "exit",
+        'F' => "food",
+        _ => TILES.iter().find(|(_, other, _)| *other == glyph).map_or("prop", |(_, _, name)| *name)
+    }
+}
+
+
+fn tile_glyph(tile: Tile) -> char {
+    TILES.iter().find(|(other, _, _)| *other == tile).map(|(_, glyph, _)| *glyph).unwrap()
+}
+
+
+/// Parses a line like `o = dynamic circle 0.5 density=2`, or returns the column of the error and the error.
+fn parse_prop_def(line: &str) -> Result<(char, PropDef), (usize, String)> {
+    let words = words(line);
+    let glyph = match words.as_slice() {
+        [(_, glyph), (_, "="), _, ..] if glyph.chars().count() == 1 => glyph.chars().next().unwrap(),
+        _ => return Err((1, "expected a glyph, '=' and a definition".to_string()))
+    };
+    if RESERVED.contains(&glyph) || TILES.iter().any(|(_, other, _)| *other == glyph) {
+        return Err((1, format!("'{}' is already a tile", glyph)));
+    }
+
+    let (column, body_type) = words[2];
+    let body_type = match body_type {
+        "static" => BodyType::Static,
+        "kinematic" => BodyType::Kinematic,
+        "dynamic" => BodyType::Dynamic,
+        _ => return Err((column, "expected static, kinematic or dynamic".to_string()))
+    };
+
+    // Missing words are reported at the end of the line.
+    let end = line.chars().count() + 1;
+    let word = |i: usize| words.get(i).copied().unwrap_or((end, ""));
+    let size = |i: usize| {
+        let (column, size) = word(i);
+        size.parse().ok().filter(|size| *size > 0.0).ok_or((column, "expected a size".to_string()))
+    };
+    let (column, shape) = word(3);
+    let (shape, options) = match shape {
+        "circle" => (PropShape::Circle(size(4)?), 5),
+        "box" => (PropShape::Box(size(4)?, size(5)?), 6),
+        _ => return Err((column, "expected circle or box".to_string()))
+    };
+
+    let mut def = PropDef {
+        shape,
+        body_type,
+        density: 1.0,
+        friction: 0.2,
+        velocity: vec2(0.0, 0.0),
+        angular_velocity: 0.0,
+        color: (255, 255, 255)
+    };
+    for (column, option) in words.iter().skip(options).copied() {
+        let mut parts = option.splitn(2, '=');
+        let (name, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        let invalid = |expected: &str| (column, format!("{} expects {}, got '{}'", name, expected, value));
+        match name {
+            "density" => def.density = value.parse().map_err(|_| invalid("a number"))?,
+            "friction" => def.friction = value.parse().map_err(|_| invalid("a number"))?,
+            "angular_velocity" => def.angular_velocity = value.parse().map_err(|_| invalid("a number"))?,
+            "velocity" => {
+                let velocity = parse_list(value, 2).ok_or_else(|| invalid("X,Y"))?;
+                def.velocity = vec2(velocity[0], velocity[1]);
+            }
+            "color" => {
+                let color = parse_list(value, 3).ok_or_else(|| invalid("R,G,B"))?;
+                def.color = (color[0], color[1], color[2]);
+            }
+            _ => return Err((column, format!("unknown option '{}'", name)))
+        }
+    }
+    Ok((glyph, def))
+}
+
+
+/// Writes a definition in the format that `parse_prop_def` reads, without the glyph.
+fn write_prop_def(def: &PropDef) -> String {
+    let body_type = match def.body_type {
+        BodyType::Static => "static",
+        BodyType::Kinematic => "kinematic",
+        BodyType::Dynamic => "dynamic"
+    };
+    let shape = match def.shape {
+        PropShape::Circle(radius) => format!("circle {}", radius),
+        PropShape::Box(width, height) => format!("box {} {}", width, height)
+    };
+    let (r, g, b) = def.color;
+    format!(
+        "{} {} density={} friction={} velocity={},{} angular_velocity={} color={},{},{}",
+        body_type, shape, def.density, def.friction, def.velocity.x, def.velocity.y, def.angular_velocity, r, g, b
+    )
+}
+
+
+/// The words of a line, and the columns they start at, counted from 1.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (column, (i, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((column + 1, i)),
+            (true, Some((start_column, start_index))) => {
+                words.push((start_column, &line[start_index..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((start_column, start_index)) = start {
+        words.push((start_column, &line[start_index..]));
+    }
+    words
+}
+
+
+/// Parses a list like `1,2,3` with `count` values.
+fn parse_list<T: FromStr>(value: &str, count: usize) -> Option<Vec<T>> {
+    let list: Vec<T> = value.split(',').map(|value| value.trim().parse().ok()).collect::<Option<_>>()?;
+    Some(list).filter(|list| list.len() == count)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn missing_spawn() {
+        let error = Level::parse("XXX\nX X\nXXX\n").unwrap_err();
+        assert_eq!(error.position, None);
+        assert_eq!(error.to_string(), "the level has no player spawn");
+    }
+
+
+    #[test]
+    fn second_spawn() {
+        let error = Level::parse("XXXXX\nXP  X\nX  PX\nXXXXX\n").unwrap_err();
+        assert_eq!(error.position, Some((3, 4)));
+        assert_eq!(error.to_string(), "line 3, column 4: second player spawn, the first is at line 2, column 2");
+    }
+
+
+    #[test]
+    fn unknown_glyph_is_ignored() {
+        let level = Level::parse("P?X\n").unwrap();
+        assert_eq!(level.spawn, vec2(0.0, 0.0));
+        assert_eq!(level.boxes, vec![vec2(2.0, 0.0)]);
+        assert!(level.tiles.is_empty());
+        assert!(level.props.is_empty());
+    }
+
+
+    #[test]
+    fn bad_prop_option() {
+        let error = Level::parse("P\n[props]\nq = dynamic box 1 1 bounce=2\n").unwrap_err();
+        assert_eq!(error.position, Some((3, 21)));
+        assert_eq!(error.to_string(), "line 3, column 21: unknown option 'bounce'");
+
+        let error = Level::parse("P\n[props]\nq = dynamic circle 1 density=heavy\n").unwrap_err();
+        assert_eq!(error.position, Some((3, 22)));
+    }
+
+
+    #[test]
+    fn write_and_parse() {
+        let source = "\
+XXXXXXX
+XP o EX
+X-I S^X
+X~~F q X
+XXXXXXX
+[props]
+q = kinematic box 2 0.5 angular_velocity=0.8 color=10,20,30
+";
+        let level = Level::parse(source).unwrap();
+        assert_eq!(level.props.len(), 2);
+        assert_eq!(Level::parse(&level.write()).unwrap(), level);
+    }
+}
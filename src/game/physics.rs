@@ -1,9 +1,15 @@
 mod noodle_cat;
 
+use std::time;
+
 use wrapped2d::{
     b2,
     user_data::NoUserData,
-    dynamics::{world::BodyHandle, contacts::Contact}
+    dynamics::{
+        world::{BodyHandle, JointHandle, callbacks::{ContactFilter, BodyAccess, FixtureAccess}},
+        body::FixtureHandle,
+        contacts::Contact
+    }
 };
 
 use lib::math::{Vec2, vec2};
@@ -14,16 +20,67 @@ use noodle_cat::NoodleCat;
 
 type B2World = b2::World<NoUserData>;
 
+/// The collision categories of tile fixtures, so that contacts can tell what kind of tile they touch. Boxes, props
+/// and the cat are in the default category.
+const ONE_WAY: u16 = 0x2;
+const ICE: u16 = 0x4;
+const STICKY: u16 = 0x8;
+const SPIKES: u16 = 0x10;
+const WATER: u16 = 0x20;
+const FOOD: u16 = 0x40;
+
+/// How much harder than gravity water pushes bodies up.
+const BUOYANCY: f32 = 1.5;
+/// How quickly water slows bodies down, per second.
+const WATER_DRAG: f32 = 2.0;
+
 pub struct World {
     world: B2World,
     ground: BodyHandle,
-    cat: NoodleCat
+    props: Vec<BodyHandle>,
+    cat: NoodleCat,
+    /// A static body for the mouse joint to hang from, since the ground is recreated when it changes.
+    drag_anchor: BodyHandle,
+    /// The mouse joint pulling the dragged body.
+    drag: Option<JointHandle>
+}
+
+/// The physical state of a single body.
+#[derive(Clone, Copy)]
+pub struct BodySnapshot {
+    pub position: Vec2,
+    pub angle: f32,
+    pub linear_velocity: Vec2,
+    pub angular_velocity: f32
+}
+
+#[derive(Clone)]
+pub struct CatSnapshot {
+    pub links: Vec<BodySnapshot>,
+    pub tail_links: Vec<BodySnapshot>,
+    /// 0 is the ground, and i + 1 is the i-th prop.
+    pub grabbed: Option<usize>,
+    pub grab_d: Option<Vec2>,
+    pub walk_length: f32,
+    pub extend_phase: f32,
+    pub contracting: bool,
+    pub contract_phase: f32
+}
+
+/// Everything needed to put the physics world back into an earlier state.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub cat: CatSnapshot,
+    pub props: Vec<BodySnapshot>
 }
 
 struct DebugDraw<'a> {
     info: &'a mut state::DebugInfo
 }
 
+/// Lets bodies pass through one-way platforms unless they are above them.
+struct OneWayFilter;
+
 
 fn to_vec2(vector: b2::Vec2) -> Vec2 {
     vec2(vector.x, vector.y)
@@ -46,6 +103,12 @@ fn b2_get_local_point(body: &b2::Body, world_point: &b2::Vec2) -> b2::Vec2 {
 }
 
 
+/// The collision category of a fixture.
+fn category(world: &B2World, (body, fixture): (BodyHandle, FixtureHandle)) -> u16 {
+    world.body(body).fixture(fixture).filter_data().category_bits
+}
+
+
 fn evaluate_contact(world: &B2World, contact: &Contact) -> (i32, b2::WorldManifold) {
     let body = world.body(contact.fixture_a().0);
     let transform_a = body.transform();
@@ -74,72 +137,344 @@ fn evaluate_contact(world: &B2World, contact: &Contact) -> (i32, b2::WorldManifo
 
 
 impl World {
-    pub fn new(state: &state::State) -> World {
+    /// Creates the world along with the props of the ground, which replace the props in `state`.
+    pub fn new(state: &mut state::State) -> World {
         let mut world = B2World::new(&b2::Vec2 { x: 0.0, y: -10.0 });
+        world.set_contact_filter(Box::new(OneWayFilter));
 
         let ground = world.create_body(&b2::BodyDef::new());
+        let drag_anchor = world.create_body(&b2::BodyDef::new());
 
         let cat = NoodleCat::new(&mut world, &state.cat);
 
-        let test = world.create_body(&b2::BodyDef {
-            body_type: b2::BodyType::Dynamic,
-            position: b2::Vec2 { x: 26.5, y: -9.0 },
-            ..b2::BodyDef::new()
-        });
-        let mut body = world.body_mut(test);
-        let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 0.5);
-        body.create_fast_fixture(&circle, 1.0);
-        drop(body);
+        let mut world = World {
+            world,
+            ground,
+            props: Vec::new(),
+            cat,
+            drag_anchor,
+            drag: None
+        };
+        state.props.clear();
+        for (p, def) in state.ground.props.iter() {
+            world.spawn_prop(&mut state.props, *p, def);
+        }
+        world
+    }
 
-        let test = world.create_body(&b2::BodyDef {
-            body_type: b2::BodyType::Dynamic,
-            position: b2::Vec2 { x: 26.5, y: -10.0 },
-            ..b2::BodyDef::new()
-        });
-        let mut body = world.body_mut(test);
-        let square = b2::PolygonShape::new_box(0.5, 0.5);
-        body.create_fast_fixture(&square, 1.0);
-        drop(body);
 
-        let test = world.create_body(&b2::BodyDef {
-            body_type: b2::BodyType::Kinematic,
-            position: b2::Vec2 { x: 25.5, y: -6.0 },
-            angular_velocity: std::f32::consts::PI * 0.25,
+    /// Adds a prop centered at `p`, and its transform to `props`.
+    pub fn spawn_prop(&mut self, props: &mut Vec<state::Prop>, p: Vec2, def: &state::PropDef) {
+        let prop = self.world.create_body(&b2::BodyDef {
+            body_type: match def.body_type {
+                state::BodyType::Static => b2::BodyType::Static,
+                state::BodyType::Kinematic => b2::BodyType::Kinematic,
+                state::BodyType::Dynamic => b2::BodyType::Dynamic
+            },
+            position: to_bvec(p),
+            linear_velocity: to_bvec(def.velocity),
+            angular_velocity: def.angular_velocity,
             ..b2::BodyDef::new()
         });
-        let mut body = world.body_mut(test);
-        let rectangle = b2::PolygonShape::new_box(2.0, 0.5);
-        body.create_fast_fixture(&rectangle, 1.0);
-        drop(body);
-
-        World {
-            world,
-            ground,
-            cat
+        let mut fixture = b2::FixtureDef::new();
+        fixture.density = def.density;
+        fixture.friction = def.friction;
+        let mut body = self.world.body_mut(prop);
+        match def.shape {
+            state::PropShape::Circle(radius) => {
+                let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, radius);
+                body.create_fixture(&circle, &mut fixture);
+            }
+            state::PropShape::Box(width, height) => {
+                let rectangle = b2::PolygonShape::new_box(width * 0.5, height * 0.5);
+                body.create_fixture(&rectangle, &mut fixture);
+            }
         }
+        drop(body);
+        self.props.push(prop);
+        props.push(state::Prop {
+            shape: def.shape,
+            color: def.color,
+            position: p,
+            angle: 0.0,
+            previous_position: p,
+            previous_angle: 0.0
+        });
     }
 
 
     pub fn step(&mut self, state: &mut state::State, delta_time: f32) {
-        let ground = &mut state.ground;
-        let cat = &mut state.cat;
+        self.update_ground(&mut state.ground);
+        self.apply_water();
 
-        if ground.dirty.contains(state::DirtyFlags::PHYSICS) {
-            self.world.destroy_body(self.ground);
-            self.ground = self.world.create_body(&b2::BodyDef::new());
-            let mut body = self.world.body_mut(self.ground);
-            for p in ground.boxes.iter().copied() {
-                let square = b2::PolygonShape::new_oriented_box(0.5, 0.5, &to_bvec(p), 0.0);
-                body.create_fast_fixture(&square, 1.0);
-            }
-            ground.dirty -= state::DirtyFlags::PHYSICS;
-        }
+        let cat = &mut state.cat;
+        let profile = &mut state.debug.profile;
 
+        let start = time::Instant::now();
         self.cat.control(&mut self.world, cat, delta_time);
 
         self.world.step(delta_time, 5, 5);
+        profile.current[state::ProfileSection::PhysicsStep as usize] += start.elapsed();
 
+        let start = time::Instant::now();
         self.cat.update(cat, &self.world);
+        profile.current[state::ProfileSection::NoodleCatUpdate as usize] += start.elapsed();
+
+        self.update_props(&mut state.props);
+    }
+
+
+    fn update_props(&self, props: &mut [state::Prop]) {
+        for (body, prop) in self.props.iter().zip(props.iter_mut()) {
+            let body = self.world.body(*body);
+            prop.position = to_vec2(*body.position());
+            prop.angle = body.angle();
+        }
+    }
+
+
+    fn update_ground(&mut self, ground: &mut state::Ground) {
+        if !ground.dirty.contains(state::DirtyFlags::PHYSICS) {
+            return;
+        }
+        // The fixtures are replaced instead of the body, so that the cat can keep holding on to the ground.
+        let mut body = self.world.body_mut(self.ground);
+        let fixtures: Vec<_> = body.fixtures().map(|(fixture, _)| fixture).collect();
+        for fixture in fixtures {
+            body.destroy_fixture(fixture);
+        }
+        for p in ground.boxes.iter().copied() {
+            let square = b2::PolygonShape::new_oriented_box(0.5, 0.5, &to_bvec(p), 0.0);
+            body.create_fast_fixture(&square, 1.0);
+        }
+        for (p, tile) in ground.tiles.iter().copied() {
+            let mut fixture = b2::FixtureDef::new();
+            let (half_height, offset) = match tile {
+                state::Tile::OneWay => {
+                    fixture.filter.category_bits = ONE_WAY;
+                    (0.125, 0.375)
+                }
+                state::Tile::Ice => {
+                    fixture.filter.category_bits = ICE;
+                    fixture.friction = 0.0;
+                    (0.5, 0.0)
+                }
+                state::Tile::Sticky => {
+                    fixture.filter.category_bits = STICKY;
+                    fixture.friction = 1.0;
+                    (0.5, 0.0)
+                }
+                state::Tile::Spikes => {
+                    fixture.filter.category_bits = SPIKES;
+                    (0.25, -0.25)
+                }
+                state::Tile::Water => {
+                    fixture.filter.category_bits = WATER;
+                    fixture.is_sensor = true;
+                    (0.5, 0.0)
+                }
+            };
+            let shape = b2::PolygonShape::new_oriented_box(0.5, half_height, &to_bvec(p + vec2(0.0, offset)), 0.0);
+            body.create_fixture(&shape, &mut fixture);
+        }
+        for p in ground.food.iter().copied() {
+            let circle = b2::CircleShape::new_with(to_bvec(p), 0.3);
+            let mut fixture = b2::FixtureDef::new();
+            fixture.filter.category_bits = FOOD;
+            fixture.is_sensor = true;
+            body.create_fixture(&circle, &mut fixture);
+        }
+        ground.dirty -= state::DirtyFlags::PHYSICS;
+        debug!("rebuilt the ground from {} boxes and {} other tiles", ground.boxes.len(), ground.tiles.len());
+    }
+
+
+    /// Pushes the dynamic bodies in water up and slows them down.
+    fn apply_water(&mut self) {
+        let mut bodies = Vec::new();
+        for contact in self.world.contacts() {
+            if !contact.is_touching() {
+                continue;
+            }
+            let fixtures = [(contact.fixture_a(), contact.fixture_b()), (contact.fixture_b(), contact.fixture_a())];
+            for (water, (body, fixture)) in fixtures.iter().copied() {
+                if category(&self.world, water) != WATER || bodies.contains(&body) {
+                    continue;
+                }
+                let other = self.world.body(body);
+                if other.body_type() == b2::BodyType::Dynamic && !other.fixture(fixture).is_sensor() {
+                    bodies.push(body);
+                }
+            }
+        }
+        let gravity = to_vec2(self.world.gravity());
+        for body in bodies {
+            let mut body = self.world.body_mut(body);
+            let mass = body.mass();
+            let force = -gravity * BUOYANCY * mass - to_vec2(*body.linear_velocity()) * WATER_DRAG * mass;
+            body.apply_force_to_center(&to_bvec(force), true);
+        }
+    }
+
+
+    /// The positions of the tiles of a category that the cat's body touches.
+    fn touched_tiles(&self, category_bits: u16) -> Vec<Vec2> {
+        let mut tiles = Vec::new();
+        for link in self.cat.bodies() {
+            for (_, contact) in self.world.body(link).contacts() {
+                if !contact.is_touching() {
+                    continue;
+                }
+                let (cat, ground) = if contact.fixture_a().0 == link {
+                    (contact.fixture_a(), contact.fixture_b())
+                }
+                else {
+                    (contact.fixture_b(), contact.fixture_a())
+                };
+                // The head sensor reaches further than the head.
+                if ground.0 != self.ground || self.world.body(link).fixture(cat.1).is_sensor() {
+                    continue;
+                }
+                if category(&self.world, ground) != category_bits {
+                    continue;
+                }
+                // Tiles are on whole units, and their fixtures don't reach into other tiles.
+                let aabb = self.world.body(self.ground).fixture(ground.1).aabb(0).clone();
+                let center = (to_vec2(aabb.lower) + to_vec2(aabb.upper)) * 0.5;
+                let p = vec2(center.x.round(), center.y.round());
+                if !tiles.contains(&p) {
+                    tiles.push(p);
+                }
+            }
+        }
+        tiles
+    }
+
+
+    pub fn touches_spikes(&self) -> bool {
+        !self.touched_tiles(SPIKES).is_empty()
+    }
+
+
+    /// The food that the cat touches.
+    pub fn touched_food(&self) -> Vec<Vec2> {
+        self.touched_tiles(FOOD)
+    }
+
+
+    /// The bodies that a cat snapshot can refer to.
+    fn grabbable_bodies(&self) -> Vec<BodyHandle> {
+        std::iter::once(self.ground).chain(self.props.iter().copied()).collect()
+    }
+
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cat: self.cat.snapshot(&self.world, &self.grabbable_bodies()),
+            props: self.props.iter().map(|prop| BodySnapshot::new(&self.world.body(*prop))).collect()
+        }
+    }
+
+
+    /// Puts the world back into the state of `snapshot`, and updates the cat path in `state` to match it.
+    pub fn restore(&mut self, state: &mut state::State, snapshot: &Snapshot) {
+        // The cat's links may be recreated, so a dragged link could be gone.
+        self.end_drag();
+        self.update_ground(&mut state.ground);
+
+        for (prop, prop_snapshot) in self.props.iter().zip(snapshot.props.iter()) {
+            let mut body = self.world.body_mut(*prop);
+            body.set_transform(&to_bvec(prop_snapshot.position), prop_snapshot.angle);
+            prop_snapshot.apply_velocity(&mut body);
+            body.set_awake(true);
+        }
+
+        // Rewinding restores a snapshot on every step, so the cat is only recreated when its links were added or
+        // removed.
+        let bodies = self.grabbable_bodies();
+        if !self.cat.restore_in_place(&mut self.world, &snapshot.cat, &bodies) {
+            let cat = NoodleCat::restore(&mut self.world, &snapshot.cat, &bodies);
+            std::mem::replace(&mut self.cat, cat).destroy(&mut self.world);
+        }
+        self.cat.update(&mut state.cat, &self.world);
+        self.update_props(&mut state.props);
+        trace!("restored {} links and {} props", snapshot.cat.links.len(), snapshot.props.len());
+    }
+
+
+    /// Moves the cat so that its head is at `p`, letting go of anything it's holding.
+    pub fn teleport(&mut self, state: &mut state::State, p: Vec2) {
+        let mut snapshot = self.snapshot();
+        let cat = &mut snapshot.cat;
+        let head = cat.links.last().map_or(p, |link| link.position);
+        for link in cat.links.iter_mut().chain(cat.tail_links.iter_mut()) {
+            link.position += p - head;
+            link.linear_velocity = vec2(0.0, 0.0);
+            link.angular_velocity = 0.0;
+        }
+        cat.grabbed = None;
+        cat.grab_d = None;
+        self.restore(state, &snapshot);
+    }
+
+
+    pub fn set_gravity(&mut self, gravity: Vec2) {
+        self.world.set_gravity(&to_bvec(gravity));
+    }
+
+
+    /// Starts dragging the dynamic body under `p` with a mouse joint, if there is one.
+    pub fn start_drag(&mut self, p: Vec2) {
+        self.end_drag();
+        let point = to_bvec(p);
+        let body = self.world.bodies().find(|(_, body)| {
+            let body = body.borrow();
+            body.body_type() == b2::BodyType::Dynamic && body.fixtures().any(|(_, fixture)| {
+                let fixture = fixture.borrow();
+                !fixture.is_sensor() && fixture.test_point(&point)
+            })
+        }).map(|(handle, _)| handle);
+        let body = match body {
+            Some(body) => body,
+            None => return
+        };
+        let mut def = b2::MouseJointDef::new(self.drag_anchor, body);
+        def.target = point;
+        def.max_force = 1000.0 * self.world.body(body).mass();
+        let joint = self.world.create_joint(&def);
+        self.world.body_mut(body).set_awake(true);
+        self.drag = Some(joint);
+    }
+
+
+    /// Moves the point that the dragged body is pulled towards.
+    pub fn drag_to(&mut self, p: Vec2) {
+        let joint = match self.drag {
+            Some(joint) => joint,
+            None => return
+        };
+        // The joint is destroyed along with its body, e.g. when the cat contracts.
+        if !self.joint_exists(joint) {
+            self.drag = None;
+            return;
+        }
+        if let b2::UnknownJoint::Mouse(joint) = &mut **self.world.joint_mut(joint) {
+            joint.set_target(&to_bvec(p));
+        }
+    }
+
+
+    pub fn end_drag(&mut self) {
+        if let Some(joint) = self.drag.take() {
+            if self.joint_exists(joint) {
+                self.world.destroy_joint(joint);
+            }
+        }
+    }
+
+
+    fn joint_exists(&self, joint: JointHandle) -> bool {
+        self.world.joints().any(|(handle, _)| handle == joint)
     }
 
 
@@ -198,6 +533,65 @@ impl World {
 }
 
 
+impl ContactFilter<NoUserData> for OneWayFilter {
+    fn should_collide(
+        &mut self,
+        body_a: BodyAccess<NoUserData>, fixture_a: FixtureAccess<NoUserData>,
+        body_b: BodyAccess<NoUserData>, fixture_b: FixtureAccess<NoUserData>
+    ) -> bool {
+        // The default filtering of Box2D.
+        let (filter_a, filter_b) = (fixture_a.filter_data(), fixture_b.filter_data());
+        if filter_a.group_index == filter_b.group_index && filter_a.group_index != 0 {
+            return filter_a.group_index > 0;
+        }
+        if filter_a.mask_bits & filter_b.category_bits == 0 || filter_b.mask_bits & filter_a.category_bits == 0 {
+            return false;
+        }
+        // Contacts are only filtered when they start, so a body that comes from below passes through the whole
+        // platform.
+        if filter_a.category_bits == ONE_WAY {
+            return Self::is_above(&fixture_a, &body_b, &fixture_b);
+        }
+        if filter_b.category_bits == ONE_WAY {
+            return Self::is_above(&fixture_b, &body_a, &fixture_a);
+        }
+        true
+    }
+}
+
+
+impl OneWayFilter {
+    fn is_above(platform: &b2::Fixture, body: &b2::Body, fixture: &b2::Fixture) -> bool {
+        let top = platform.aabb(0).upper.y;
+        // The head sensor reaches below the head.
+        if fixture.is_sensor() {
+            body.world_center().y > top
+        }
+        else {
+            fixture.aabb(0).lower.y > top - 0.25
+        }
+    }
+}
+
+
+impl BodySnapshot {
+    fn new(body: &b2::Body) -> BodySnapshot {
+        BodySnapshot {
+            position: to_vec2(*body.position()),
+            angle: body.angle(),
+            linear_velocity: to_vec2(*body.linear_velocity()),
+            angular_velocity: body.angular_velocity()
+        }
+    }
+
+
+    fn apply_velocity(&self, body: &mut b2::Body) {
+        body.set_linear_velocity(&to_bvec(self.linear_velocity));
+        body.set_angular_velocity(self.angular_velocity);
+    }
+}
+
+
 impl From<&b2::Color> for state::DebugColor {
     fn from(color: &b2::Color) -> state::DebugColor {
         state::DebugColor(
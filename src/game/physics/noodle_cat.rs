@@ -9,10 +9,15 @@ use lib::math::{Vec2, vec2, wrap_angle};
 use super::{
     state,
     B2World,
+    BodySnapshot,
+    CatSnapshot,
     to_vec2,
     to_bvec,
     b2_get_local_point,
-    evaluate_contact
+    evaluate_contact,
+    category,
+    ICE,
+    STICKY
 };
 
 
@@ -36,17 +41,181 @@ impl NoodleCat {
         let path = &cat.path;
         let mut links: VecDeque<_> = VecDeque::with_capacity(path.len());
         let mut muscles: VecDeque<_> = VecDeque::with_capacity(path.len().saturating_sub(1));
-        let mut link = world.create_body(
+        let mut link = Self::create_link(world, path[0], 0.0);
+        let butt = link;
+        links.push_back(link);
+        for (p, p2) in path.iter().copied().zip(path.iter().copied().skip(1)) {
+            let next = Self::create_link(world, p2, 0.0);
+            muscles.push_back(Self::connect_links(world, link, next, p - p2));
+            link = next;
+            links.push_back(link);
+        }
+        let head = link;
+
+        let tail = &cat.tail;
+        let mut tail_links: Vec<_> = Vec::with_capacity(tail.len());
+        let mut link = Self::create_tail_link(world, tail[0], 0.0);
+        Self::connect_tail(world, butt, link, path[0] - tail[0]);
+        tail_links.push(link);
+        for (p, p2) in tail.iter().copied().zip(tail.iter().copied().skip(1)) {
+            let next = Self::create_tail_link(world, p2, 0.0);
+            Self::connect_tail_links(world, link, next, p - p2);
+            link = next;
+            tail_links.push(link);
+        }
+
+        let head_sensor = Self::create_head_sensor(world, head);
+
+        NoodleCat {
+            links,
+            muscles,
+            tail_links,
+            head_sensor,
+            grab: None,
+            grabbed: None,
+            grab_d: None,
+            walk_length: 0.0,
+            extend_phase: 1.0,
+            contracting: false,
+            contract_phase: 0.0
+        }
+    }
+
+
+    /// Recreates a cat from a snapshot. `bodies` are the bodies that the snapshot's grab index refers to.
+    pub fn restore(world: &mut B2World, snapshot: &CatSnapshot, bodies: &[BodyHandle]) -> NoodleCat {
+        // The links of a new cat point along the x axis, so the anchors are the same as for a new cat.
+        let mut links: VecDeque<_> = VecDeque::with_capacity(snapshot.links.len());
+        let mut muscles: VecDeque<_> = VecDeque::with_capacity(snapshot.links.len().saturating_sub(1));
+        for link_snapshot in snapshot.links.iter() {
+            let link = Self::create_link(world, link_snapshot.position, link_snapshot.angle);
+            link_snapshot.apply_velocity(&mut world.body_mut(link));
+            if let Some(previous) = links.back().copied() {
+                muscles.push_back(Self::connect_links(world, previous, link, vec2(-0.1, 0.0)));
+            }
+            links.push_back(link);
+        }
+
+        let mut tail_links: Vec<_> = Vec::with_capacity(snapshot.tail_links.len());
+        for link_snapshot in snapshot.tail_links.iter() {
+            let link = Self::create_tail_link(world, link_snapshot.position, link_snapshot.angle);
+            link_snapshot.apply_velocity(&mut world.body_mut(link));
+            match tail_links.last().copied() {
+                Some(previous) => Self::connect_tail_links(world, previous, link, vec2(0.1, 0.0)),
+                None => Self::connect_tail(world, links.front().copied().unwrap(), link, vec2(0.4, 0.0))
+            }
+            tail_links.push(link);
+        }
+
+        let head = links.back().copied().unwrap();
+        let head_sensor = Self::create_head_sensor(world, head);
+
+        let grabbed = snapshot.grabbed.and_then(|index| bodies.get(index).copied());
+        let grab = grabbed.map(|other| Self::hold(world, head, other));
+
+        NoodleCat {
+            links,
+            muscles,
+            tail_links,
+            head_sensor,
+            grab,
+            grabbed,
+            grab_d: snapshot.grab_d.filter(|_| grab.is_some()),
+            walk_length: snapshot.walk_length,
+            extend_phase: snapshot.extend_phase,
+            contracting: snapshot.contracting,
+            contract_phase: snapshot.contract_phase
+        }
+    }
+
+
+    /// Puts the cat into the state of a snapshot without recreating its bodies, and returns whether it could, which is
+    /// when the snapshot has as many links.
+    pub fn restore_in_place(&mut self, world: &mut B2World, snapshot: &CatSnapshot, bodies: &[BodyHandle]) -> bool {
+        if self.links.len() != snapshot.links.len() || self.tail_links.len() != snapshot.tail_links.len() {
+            return false;
+        }
+        let link_snapshots = snapshot.links.iter().chain(snapshot.tail_links.iter());
+        for (link, link_snapshot) in self.bodies().zip(link_snapshots) {
+            let mut body = world.body_mut(link);
+            body.set_transform(&to_bvec(link_snapshot.position), link_snapshot.angle);
+            link_snapshot.apply_velocity(&mut body);
+            body.set_awake(true);
+        }
+
+        if let Some(grab) = self.grab.take() {
+            world.destroy_joint(grab);
+        }
+        let head = self.links.back().copied().unwrap();
+        self.grabbed = snapshot.grabbed.and_then(|index| bodies.get(index).copied());
+        self.grab = self.grabbed.map(|other| Self::hold(world, head, other));
+        self.grab_d = snapshot.grab_d.filter(|_| self.grab.is_some());
+        self.walk_length = snapshot.walk_length;
+        self.extend_phase = snapshot.extend_phase;
+        self.contracting = snapshot.contracting;
+        self.contract_phase = snapshot.contract_phase;
+        true
+    }
+
+
+    /// Joins the head to another body where the head is.
+    fn hold(world: &mut B2World, head: BodyHandle, other: BodyHandle) -> JointHandle {
+        let head_body = world.body(head);
+        let other_body = world.body(other);
+        let anchor = *head_body.position();
+        let def = b2::RevoluteJointDef {
+            collide_connected: true,
+            local_anchor_a: b2_get_local_point(&head_body, &anchor),
+            local_anchor_b: b2_get_local_point(&other_body, &anchor),
+            ..b2::RevoluteJointDef::new(head, other)
+        };
+        drop(head_body);
+        drop(other_body);
+        world.create_joint(&def)
+    }
+
+
+    /// `bodies` are the bodies that a grab can be stored as an index into.
+    pub fn snapshot(&self, world: &B2World, bodies: &[BodyHandle]) -> CatSnapshot {
+        let body_snapshot = |link: BodyHandle| BodySnapshot::new(&world.body(link));
+        CatSnapshot {
+            links: self.links.iter().copied().map(body_snapshot).collect(),
+            tail_links: self.tail_links.iter().copied().map(body_snapshot).collect(),
+            grabbed: self.grab.and(self.grabbed).and_then(|grabbed| bodies.iter().position(|body| *body == grabbed)),
+            grab_d: self.grab_d,
+            walk_length: self.walk_length,
+            extend_phase: self.extend_phase,
+            contracting: self.contracting,
+            contract_phase: self.contract_phase
+        }
+    }
+
+
+    /// Destroys all bodies of the cat, along with their joints.
+    pub fn destroy(self, world: &mut B2World) {
+        for link in self.bodies() {
+            world.destroy_body(link);
+        }
+    }
+
+
+    /// The links of the body and the tail.
+    pub fn bodies(&self) -> impl Iterator<Item = BodyHandle> + '_ {
+        self.links.iter().copied().chain(self.tail_links.iter().copied())
+    }
+
+
+    fn create_link(world: &mut B2World, p: Vec2, angle: f32) -> BodyHandle {
+        let link = world.create_body(
             &b2::BodyDef {
                 body_type: b2::BodyType::Dynamic,
-                position: to_bvec(path[0]),
+                position: to_bvec(p),
+                angle,
                 linear_damping: 2.0,
                 angular_damping: 1.0,
                 ..b2::BodyDef::new()
             }
         );
-        let butt = link;
-        let mut head = link;
         let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 0.5);
         let mut fixture = b2::FixtureDef::new();
         fixture.density = 1.0;
@@ -54,62 +223,84 @@ impl NoodleCat {
         fixture.friction = 0.0;
         fixture.filter.group_index = -1;
         world.body_mut(link).create_fixture(&circle, &mut fixture);
-        links.push_back(link);
-        for (p, p2) in path.iter().copied().zip(path.iter().copied().skip(1)) {
-            let next = world.create_body(
-                &b2::BodyDef {
-                    body_type: b2::BodyType::Dynamic,
-                    position: to_bvec(p2),
-                    linear_damping: 2.0,
-                    angular_damping: 1.0,
-                    ..b2::BodyDef::new()
-                }
-            );
-            world.body_mut(next).create_fixture(&circle, &mut fixture);
-            let d = p - p2;
-            world.create_joint(
-                &b2::RevoluteJointDef {
-                    local_anchor_b: to_bvec(d),
-                    lower_angle: -std::f32::consts::PI * 0.06,
-                    upper_angle: std::f32::consts::PI * 0.06,
-                    enable_limit: true,
-                    ..b2::RevoluteJointDef::new(link, next)
-                }
-            );
-            let muscle = world.create_joint(
-                &b2::MotorJointDef {
-                    max_force: 0.0,
-                    max_torque: 10.0,
-                    correction_factor: 1.0,
-                    ..b2::MotorJointDef::new(link, next)
-                }
-            );
-            link = next;
-            head = link;
-            links.push_back(link);
-            muscles.push_back(muscle);
-        }
+        link
+    }
 
-        let tail = &cat.tail;
-        let mut tail_links: Vec<_> = Vec::with_capacity(tail.len());
-        let mut link = world.create_body(
+
+    /// Connects two links and returns the muscle between them.
+    fn connect_links(world: &mut B2World, link: BodyHandle, next: BodyHandle, anchor: Vec2) -> JointHandle {
+        world.create_joint(
+            &b2::RevoluteJointDef {
+                local_anchor_b: to_bvec(anchor),
+                lower_angle: -std::f32::consts::PI * 0.06,
+                upper_angle: std::f32::consts::PI * 0.06,
+                enable_limit: true,
+                ..b2::RevoluteJointDef::new(link, next)
+            }
+        );
+        world.create_joint(
+            &b2::MotorJointDef {
+                max_force: 0.0,
+                max_torque: 10.0,
+                correction_factor: 1.0,
+                ..b2::MotorJointDef::new(link, next)
+            }
+        )
+    }
+
+
+    fn create_tail_link(world: &mut B2World, p: Vec2, angle: f32) -> BodyHandle {
+        let link = world.create_body(
             &b2::BodyDef {
                 body_type: b2::BodyType::Dynamic,
-                position: to_bvec(tail[0]),
+                position: to_bvec(p),
+                angle,
                 linear_damping: 2.0,
                 angular_damping: 1.0,
                 gravity_scale: 0.1,
                 ..b2::BodyDef::new()
             }
         );
-        let d = path[0] - tail[0];
+        let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 0.2);
+        let mut fixture = b2::FixtureDef::new();
+        fixture.density = 1.0;
+        fixture.restitution = 0.0;
+        fixture.friction = 0.0;
+        fixture.filter.group_index = -1;
+        world.body_mut(link).create_fixture(&circle, &mut fixture);
+        link
+    }
+
+
+    fn connect_tail_links(world: &mut B2World, link: BodyHandle, next: BodyHandle, anchor: Vec2) {
+        world.create_joint(
+            &b2::RevoluteJointDef {
+                local_anchor_b: to_bvec(anchor),
+                lower_angle: -std::f32::consts::PI * 0.1,
+                upper_angle: std::f32::consts::PI * 0.1,
+                enable_limit: true,
+                ..b2::RevoluteJointDef::new(link, next)
+            }
+        );
+        world.create_joint(
+            &b2::MotorJointDef {
+                max_force: 0.0,
+                max_torque: 0.5,
+                correction_factor: 0.5,
+                ..b2::MotorJointDef::new(link, next)
+            }
+        );
+    }
+
+
+    fn connect_tail(world: &mut B2World, butt: BodyHandle, tail_root: BodyHandle, anchor: Vec2) {
         world.create_joint(
             &b2::RevoluteJointDef {
-                local_anchor_b: to_bvec(d),
+                local_anchor_b: to_bvec(anchor),
                 lower_angle: -std::f32::consts::PI * 0.25,
                 upper_angle: std::f32::consts::PI * 0.25,
                 enable_limit: true,
-                ..b2::RevoluteJointDef::new(butt, link)
+                ..b2::RevoluteJointDef::new(butt, tail_root)
             }
         );
         world.create_joint(
@@ -117,70 +308,18 @@ impl NoodleCat {
                 max_force: 0.0,
                 max_torque: 5.0,
                 correction_factor: 0.5,
-                ..b2::MotorJointDef::new(butt, link)
+                ..b2::MotorJointDef::new(butt, tail_root)
             }
         );
-        let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 0.2);
-        let mut fixture = b2::FixtureDef::new();
-        fixture.density = 1.0;
-        fixture.restitution = 0.0;
-        fixture.friction = 0.0;
-        fixture.filter.group_index = -1;
-        world.body_mut(link).create_fixture(&circle, &mut fixture);
-        tail_links.push(link);
-        for (p, p2) in tail.iter().copied().zip(tail.iter().copied().skip(1)) {
-            let next = world.create_body(
-                &b2::BodyDef {
-                    body_type: b2::BodyType::Dynamic,
-                    position: to_bvec(p2),
-                    linear_damping: 2.0,
-                    angular_damping: 1.0,
-                    gravity_scale: 0.1,
-                    ..b2::BodyDef::new()
-                }
-            );
-            world.body_mut(next).create_fixture(&circle, &mut fixture);
-            let d = p - p2;
-            world.create_joint(
-                &b2::RevoluteJointDef {
-                    local_anchor_b: to_bvec(d),
-                    lower_angle: -std::f32::consts::PI * 0.1,
-                    upper_angle: std::f32::consts::PI * 0.1,
-                    enable_limit: true,
-                    ..b2::RevoluteJointDef::new(link, next)
-                }
-            );
-            world.create_joint(
-                &b2::MotorJointDef {
-                    max_force: 0.0,
-                    max_torque: 0.5,
-                    correction_factor: 0.5,
-                    ..b2::MotorJointDef::new(link, next)
-                }
-            );
-            link = next;
-            tail_links.push(link);
-        }
+    }
+
 
+    fn create_head_sensor(world: &mut B2World, head: BodyHandle) -> FixtureHandle {
         let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 1.0);
         let mut fixture = b2::FixtureDef::new();
         fixture.is_sensor = true;
         fixture.filter.group_index = -1;
-        let head_sensor = world.body_mut(head).create_fixture(&circle, &mut fixture);
-
-        NoodleCat {
-            links,
-            muscles,
-            tail_links,
-            head_sensor,
-            grab: None,
-            grabbed: None,
-            grab_d: None,
-            walk_length: 0.0,
-            extend_phase: 1.0,
-            contracting: false,
-            contract_phase: 0.0
-        }
+        world.body_mut(head).create_fixture(&circle, &mut fixture)
     }
 
 
@@ -226,6 +365,7 @@ impl NoodleCat {
         let mut separation = std::f32::INFINITY;
         let mut other = None;
         let mut normal = vec2(0.0, 0.0);
+        let mut sticky = false;
         let head = self.links.back().copied().unwrap();
         for (_, contact) in world.body(head).contacts() {
             if !contact.is_touching() {
@@ -243,26 +383,37 @@ impl NoodleCat {
                 continue;
             }
             let (_, manifold) = evaluate_contact(world, &*contact);
+            let (other_fixture, contact_normal) = if is_a_sensor {
+                (contact.fixture_b(), to_vec2(manifold.normal))
+            }
+            else {
+                (contact.fixture_a(), -to_vec2(manifold.normal))
+            };
+            // Water and food are sensors, which overlap the head sensor without being anything to hold onto.
+            if world.body(other_fixture.0).fixture(other_fixture.1).is_sensor() {
+                continue;
+            }
+            let other_category = category(world, other_fixture);
+            // Ice can only be held onto from above.
+            if other_category == ICE && contact_normal.y > -0.7 {
+                continue;
+            }
             // TODO: Prioritize contacts according to direction of movement and currently grabbed body.
             // Select the closest contact.
             if manifold.separations[0] < separation {
                 separation = manifold.separations[0];
-                if is_a_sensor {
-                    other = Some(contact.fixture_b().0);
-                    normal = to_vec2(manifold.normal);
-                }
-                else {
-                    other = Some(contact.fixture_a().0);
-                    normal = -to_vec2(manifold.normal);
-                }
+                other = Some(other_fixture.0);
+                normal = contact_normal;
+                sticky = other_category == STICKY;
             }
         }
         // Grab if the paws can reach the ground and the direction of movement is not pointing away from it.
-        // Release if the direction of movement points away from the ground.
-        let mut grab = separation < -0.3 || (self.grab.is_some() && separation.is_finite());
+        // Release if the direction of movement points away from the ground, unless it's sticky.
+        let reach = if sticky { -0.1 } else { -0.3 };
+        let mut grab = separation < reach || (self.grab.is_some() && separation.is_finite());
         if grab {
             if let Some(direction) = cat.direction {
-                grab = Vec2::from_angle(direction).dot(normal) > -0.8;
+                grab = sticky || Vec2::from_angle(direction).dot(normal) > -0.8;
             }
             else if self.grab.is_none() {
                 grab = false;
@@ -291,7 +442,7 @@ impl NoodleCat {
                 let projection_length = tangent.dot(d);
                 // Normalize the projected movement unless it's directed into the ground.
                 if projection_length.abs() > 0.2 {
-                    self.walk_length = 4.0 * delta_time;
+                    self.walk_length = 4.0 * cat.speed * delta_time;
                     other_anchor += tangent * projection_length.signum() * self.walk_length;
                 }
             }
@@ -323,15 +474,15 @@ impl NoodleCat {
                 let mut body = world.body_mut(*self.links.back().unwrap());
                 let d = Vec2::from_angle(direction);
                 if cat.flying {
-                    body.set_linear_velocity(&to_bvec(d * 5.0));
+                    body.set_linear_velocity(&to_bvec(d * 5.0 * cat.speed));
                 }
                 else {
                     // Apply swimming force proportional to cat length.
-                    let force = cat.path.len() as f32;
+                    let force = cat.path.len() as f32 * cat.speed;
                     body.apply_force_to_center(&to_bvec(d * force), true);
                     drop(body);
                     Self::control_movement(world, cat, &mut control_iter);
-                    self.walk_length = 8.0 * delta_time;
+                    self.walk_length = 8.0 * cat.speed * delta_time;
                 }
             }
             Self::control_relaxed(world, &mut control_iter);
@@ -370,50 +521,14 @@ impl NoodleCat {
                 let angle = world.body(previous).angle();
                 let d = Vec2::from_angle(angle);
                 let p2 = p + d * 0.1;
-                let link = world.create_body(
-                    &b2::BodyDef {
-                        body_type: b2::BodyType::Dynamic,
-                        position: to_bvec(p2),
-                        angle,
-                        linear_damping: 2.0,
-                        angular_damping: 1.0,
-                        ..b2::BodyDef::new()
-                    }
-                );
-                let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 0.5);
-                let mut fixture = b2::FixtureDef::new();
-                fixture.density = 1.0;
-                fixture.restitution = 0.0;
-                fixture.friction = 0.0;
-                fixture.filter.group_index = -1;
-                world.body_mut(link).create_fixture(&circle, &mut fixture);
-                world.create_joint(
-                    &b2::RevoluteJointDef {
-                        local_anchor_b: b2::Vec2 { x: -0.1, y: 0.0 },
-                        lower_angle: -std::f32::consts::PI * 0.06,
-                        upper_angle: std::f32::consts::PI * 0.06,
-                        enable_limit: true,
-                        ..b2::RevoluteJointDef::new(previous, link)
-                    }
-                );
-                let muscle = world.create_joint(
-                    &b2::MotorJointDef {
-                        max_force: 0.0,
-                        max_torque: 10.0,
-                        correction_factor: 1.0,
-                        ..b2::MotorJointDef::new(previous, link)
-                    }
-                );
+                let link = Self::create_link(world, p2, angle);
+                let muscle = Self::connect_links(world, previous, link, vec2(-0.1, 0.0));
                 self.links.push_back(link);
                 self.muscles.push_back(muscle);
 
                 // Recreate head sensor.
                 world.body_mut(previous).destroy_fixture(self.head_sensor);
-                let circle = b2::CircleShape::new_with(b2::Vec2 { x: 0.0, y: 0.0 }, 1.0);
-                let mut fixture = b2::FixtureDef::new();
-                fixture.is_sensor = true;
-                fixture.filter.group_index = -1;
-                self.head_sensor = world.body_mut(link).create_fixture(&circle, &mut fixture);
+                self.head_sensor = Self::create_head_sensor(world, link);
 
                 self.extend_phase -= 1.0;
                 cat.energy -= 1;
@@ -432,23 +547,7 @@ impl NoodleCat {
             // TODO: Smoothly interpolate the tail root connection.
             let butt = self.links.front().copied().unwrap();
             let tail_root = self.tail_links.first().copied().unwrap();
-            world.create_joint(
-                &b2::RevoluteJointDef {
-                    local_anchor_b: b2::Vec2 { x: 0.4, y: 0.0 },
-                    lower_angle: -std::f32::consts::PI * 0.25,
-                    upper_angle: std::f32::consts::PI * 0.25,
-                    enable_limit: true,
-                    ..b2::RevoluteJointDef::new(butt, tail_root)
-                }
-            );
-            world.create_joint(
-                &b2::MotorJointDef {
-                    max_force: 0.0,
-                    max_torque: 5.0,
-                    correction_factor: 0.5,
-                    ..b2::MotorJointDef::new(butt, tail_root)
-                }
-            );
+            Self::connect_tail(world, butt, tail_root, vec2(0.4, 0.0));
 
             self.contracting = false;
         }
@@ -3,65 +3,114 @@ mod vertex;
 mod text;
 mod debug;
 mod gui;
+mod menu;
+mod console;
+mod editor;
 mod ground;
 mod noodle_cat;
+mod props;
 
-use std::error::Error;
+use std::{
+    error::Error,
+    fs,
+    time,
+    path::Path,
+    collections::VecDeque
+};
 
 use lib::rgl;
+use lib::math::{Vec2, vec2};
 
 use super::state;
+use super::controls;
+use super::settings::Settings;
+use super::error::{GameError, ErrorKind};
 use ground::Ground;
 use noodle_cat::NoodleCat;
+use props::Props;
 
 
+/// Shows an error that stopped the game, using as little of the renderer as possible.
+pub struct ErrorScreen {
+    program: rgl::Program,
+    title_font: text::Font,
+    font: text::Font,
+    text: text::Text,
+    title_text: text::Text
+}
+
+/// The zoom of the level before the zoom setting is applied.
+const WORLD_ZOOM: f32 = 0.2;
+
+/// Shader and texture files that are reloaded when they change, relative to the working directory.
+const SPRITE_SHADERS: (&str, &str) = ("src/game/renderer/sprite.vert", "src/game/renderer/sprite.frag");
+const DEBUG_SHADERS: (&str, &str) = ("src/game/renderer/debug.vert", "src/game/renderer/debug.frag");
+const GROUND_TEXTURE: &str = "img/ground.png";
+const CAT_TEXTURE: &str = "img/cat.png";
+
 pub struct Renderer {
     sprite_program: rgl::Program,
     debug_program: rgl::Program,
     debug_renderer: debug::Renderer,
     gui: gui::GUI,
+    menu: menu::Menu,
+    console: console::Console,
+    editor: editor::Editor,
     ground_sprite: rgl::Texture,
     ground: Ground,
+    props: Props,
     cat_sprite: rgl::Texture,
     cat: NoodleCat
 }
 
 
 impl Renderer {
-    pub fn new() -> Result<Renderer, Box<dyn Error>> {
+    pub fn new() -> Result<Renderer, GameError> {
+        let gl_error = |error| GameError::new(ErrorKind::GL, "setting up the renderer", error);
         rgl::set_blend_function(Some(rgl::BlendFunction(
             rgl::BlendFactor::One,
             rgl::BlendFactor::OneMinusSourceAlpha
-        )))?;
-
-        let mut sprite_program = Self::create_program(
-            include_str!("renderer/sprite.vert"),
-            include_str!("renderer/sprite.frag")
-        )?;
-        sprite_program.set_uniform("texture0", rgl::Uniform::Integer1(0))?;
-
-        let debug_program = Self::create_program(
-            include_str!("renderer/debug.vert"),
-            include_str!("renderer/debug.frag")
-        )?;
-
-        let text_library = text::Library::new()?;
-        let debug_renderer = debug::Renderer::new(&text_library)?;
-        let gui = gui::GUI::new(&text_library)?;
-
-        let ground_sprite = Self::load_texture("img/ground.png")?;
+        ))).map_err(gl_error)?;
+
+        let mut sprite_program = Self::startup_program(
+            SPRITE_SHADERS,
+            (include_str!("renderer/sprite.vert"), include_str!("renderer/sprite.frag"))
+        ).map_err(|error| GameError::new(ErrorKind::GL, "compiling the sprite shaders", error))?;
+        sprite_program.set_uniform("texture0", rgl::Uniform::Integer1(0)).map_err(gl_error)?;
+
+        let debug_program = Self::startup_program(
+            DEBUG_SHADERS,
+            (include_str!("renderer/debug.vert"), include_str!("renderer/debug.frag"))
+        ).map_err(|error| GameError::new(ErrorKind::GL, "compiling the debug shaders", error))?;
+
+        let font_error = |error| GameError::new(ErrorKind::Font, "loading font/Roboto-Bold.ttf", error);
+        let text_library = text::Library::new().map_err(font_error)?;
+        let debug_renderer = debug::Renderer::new(&text_library).map_err(font_error)?;
+        let gui = gui::GUI::new(&text_library).map_err(font_error)?;
+        let menu = menu::Menu::new(&text_library).map_err(font_error)?;
+        let console = console::Console::new(&text_library).map_err(font_error)?;
+        let editor = editor::Editor::new(&text_library).map_err(font_error)?;
+
+        let ground_sprite = Self::load_texture(GROUND_TEXTURE)
+            .map_err(|error| GameError::new(ErrorKind::Asset, &format!("loading {}", GROUND_TEXTURE), error))?;
         let ground = Ground::new();
+        let props = Props::new().map_err(|error| GameError::new(ErrorKind::GL, "setting up the renderer", error))?;
 
-        let cat_sprite = Self::load_texture("img/cat.png")?;
-        let cat = NoodleCat::new()?;
+        let cat_sprite = Self::load_texture(CAT_TEXTURE)
+            .map_err(|error| GameError::new(ErrorKind::Asset, &format!("loading {}", CAT_TEXTURE), error))?;
+        let cat = NoodleCat::new().map_err(|error| GameError::new(ErrorKind::GL, "setting up the renderer", error))?;
 
         Ok(Renderer {
             sprite_program,
             debug_program,
             debug_renderer,
             gui,
+            menu,
+            console,
+            editor,
             ground_sprite,
             ground,
+            props,
             cat_sprite,
             cat
         })
@@ -86,6 +135,62 @@ impl Renderer {
     }
 
 
+    /// The files that `reload` can reload.
+    pub fn watched_files() -> Vec<&'static str> {
+        vec![
+            SPRITE_SHADERS.0, SPRITE_SHADERS.1,
+            DEBUG_SHADERS.0, DEBUG_SHADERS.1,
+            GROUND_TEXTURE, CAT_TEXTURE
+        ]
+    }
+
+
+    /// Reloads a changed shader or texture file. If it fails, the old shader program or texture stays in use.
+    pub fn reload(&mut self, file: &Path) -> Result<(), GameError> {
+        let context = format!("reloading {}", file.display());
+        let is = |name: &str| file == Path::new(name);
+        if is(SPRITE_SHADERS.0) || is(SPRITE_SHADERS.1) {
+            let mut program = Self::load_program(SPRITE_SHADERS)
+                .map_err(|error| GameError::new(ErrorKind::Asset, &context, error))?;
+            program.set_uniform("texture0", rgl::Uniform::Integer1(0))
+                .map_err(|error| GameError::new(ErrorKind::GL, &context, error))?;
+            self.sprite_program = program;
+        }
+        else if is(DEBUG_SHADERS.0) || is(DEBUG_SHADERS.1) {
+            self.debug_program = Self::load_program(DEBUG_SHADERS)
+                .map_err(|error| GameError::new(ErrorKind::Asset, &context, error))?;
+        }
+        else if is(GROUND_TEXTURE) {
+            self.ground_sprite = Self::load_texture(GROUND_TEXTURE)
+                .map_err(|error| GameError::new(ErrorKind::Asset, &context, error))?;
+        }
+        else if is(CAT_TEXTURE) {
+            self.cat_sprite = Self::load_texture(CAT_TEXTURE)
+                .map_err(|error| GameError::new(ErrorKind::Asset, &context, error))?;
+        }
+        Ok(())
+    }
+
+
+    /// Loads a shader program from its files, so that edits made while the game wasn't running are used too, or
+    /// compiles the embedded sources if the files are missing or broken.
+    fn startup_program(files: (&str, &str), (vertex_source, fragment_source): (&str, &str))
+        -> Result<rgl::Program, rgl::GLError>
+    {
+        Self::load_program(files).or_else(|error| {
+            warn!("using the built-in shaders instead of {} and {}: {}", files.0, files.1, error);
+            Self::create_program(vertex_source, fragment_source)
+        })
+    }
+
+
+    fn load_program((vertex_file, fragment_file): (&str, &str)) -> Result<rgl::Program, Box<dyn Error>> {
+        let vertex_source = fs::read_to_string(vertex_file)?;
+        let fragment_source = fs::read_to_string(fragment_file)?;
+        Ok(Self::create_program(&vertex_source, &fragment_source)?)
+    }
+
+
     fn load_texture(file: &str) -> Result<rgl::Texture, Box<dyn Error>> {
         let mut image = image::open(file)?.to_rgba();
         for color in image.pixels_mut() {
@@ -99,55 +204,155 @@ impl Renderer {
 
         let mut texture = rgl::Texture::new()?;
         texture.set_data(data.as_slice(), width as _, height as _)?;
+        debug!("loaded texture {} ({}x{})", file, width, height);
 
         Ok(texture)
     }
 
 
-    pub fn render(&mut self, state: &mut state::State) -> Result<(), Box<dyn Error>> {
-        let cat = &state.cat;
+    /// Renders the active scene, interpolated between the previous and the current step.
+    pub fn render(&mut self, state: &mut state::State, settings: &Settings, interpolation: f32)
+        -> Result<(), Box<dyn Error>>
+    {
+        let scene = state.scenes.last().copied().unwrap_or(state::Scene::Gameplay);
+
+        let start = time::Instant::now();
+        let camera = self.update(state, settings, scene, interpolation)?;
+        if let Some(position) = camera {
+            state.camera = state::Camera { position, zoom: WORLD_ZOOM * settings.zoom };
+        }
+        let vertices_time = start.elapsed();
+
+        let start = time::Instant::now();
+        self.submit(state, settings, scene, camera)?;
+        let submit_time = start.elapsed();
+
+        let profile = &mut state.debug.profile;
+        profile.current[state::ProfileSection::Vertices as usize] += vertices_time;
+        profile.current[state::ProfileSection::Submit as usize] += submit_time;
+        Ok(())
+    }
 
-        let zoom = 0.2;
-        let camera = cat.path.back().unwrap();
 
-        self.debug_renderer.update(&mut state.debug)?;
-        self.gui.update(&state.gui)?;
+    /// Generates the vertices of the scene, and returns the camera position if the level is visible.
+    fn update(&mut self, state: &mut state::State, settings: &Settings, scene: state::Scene, interpolation: f32)
+        -> Result<Option<Vec2>, Box<dyn Error>>
+    {
+        // The GUI is laid out on a smaller or larger screen, and scaled to fit the real one.
+        let gui_screen = state::Screen {
+            width: (state.screen.width as f32 / settings.ui_scale) as i32,
+            height: (state.screen.height as f32 / settings.ui_scale) as i32
+        };
+        self.debug_renderer.update(&mut state.debug, &settings.controls, &gui_screen)?;
+
+        // Menus are drawn on top of the frozen level, except for the title screen.
+        let mut camera = None;
+        if scene != state::Scene::Title {
+            let cat = &state.cat;
+            let path = Self::interpolate(&cat.previous_path, &cat.path, interpolation);
+            let tail = Self::interpolate(&cat.previous_tail, &cat.tail, interpolation);
+            camera = if state.editor.open { Some(state.editor.camera) } else { path.back().copied() };
+
+            self.gui.update(&state.gui, &gui_screen)?;
+            self.ground.update(&mut state.ground)?;
+            self.props.update(&state.props, interpolation)?;
+            self.cat.update(&state.cat, &path, &tail)?;
+            if state.editor.open {
+                self.editor.update(&state.editor, &gui_screen)?;
+            }
+        }
+        if scene != state::Scene::Gameplay {
+            self.menu.update(&state.menu, &gui_screen)?;
+        }
+        if state.console.open {
+            self.console.update(&state.console, &gui_screen)?;
+        }
+        Ok(camera)
+    }
 
-        self.ground.update(&mut state.ground)?;
 
-        self.cat.update(&state.cat)?;
+    fn submit(&mut self, state: &state::State, settings: &Settings, scene: state::Scene, camera: Option<Vec2>)
+        -> Result<(), Box<dyn Error>>
+    {
+        let zoom = WORLD_ZOOM * settings.zoom;
+        let ui_scale = settings.ui_scale;
+        let (width, height) = (state.screen.width as f32, state.screen.height as f32);
+        let aspect = height / width;
 
+        rgl::viewport(0, 0, state.screen.width, state.screen.height)?;
         rgl::clear(0.2, 0.15, 0.3, 1.0)?;
 
-        self.sprite_program.use_program()?;
-        Self::set_transform(&mut self.sprite_program, zoom, -camera.x, -camera.y, 1.0, 0.0)?;
+        if let Some(camera) = camera {
+            self.sprite_program.use_program()?;
+            Self::set_transform(&mut self.sprite_program, aspect, zoom, -camera.x, -camera.y, 1.0, 0.0)?;
 
-        self.cat_sprite.bind(0)?;
-        self.cat.render()?;
+            self.cat_sprite.bind(0)?;
+            self.cat.render()?;
 
-        self.ground_sprite.bind(0)?;
-        self.ground.render()?;
+            self.ground_sprite.bind(0)?;
+            self.ground.render()?;
+            self.props.render()?;
 
-        self.cat_sprite.bind(0)?;
-        self.cat.render_near()?;
+            self.cat_sprite.bind(0)?;
+            self.cat.render_near()?;
 
-        Self::set_transform(&mut self.sprite_program, 1.0 / 360.0, -640.0, 360.0, 1.0, 0.0)?;
-        self.gui.render_text()?;
+            if state.editor.open {
+                self.ground_sprite.bind(0)?;
+                self.editor.render()?;
+            }
+
+            Self::set_gui_transform(&mut self.sprite_program, width, height, ui_scale)?;
+            self.gui.render_text()?;
+            if state.editor.open {
+                self.editor.render_text()?;
+            }
+
+            self.debug_program.use_program()?;
+            Self::set_transform(&mut self.debug_program, aspect, zoom, -camera.x, -camera.y, 1.0, 0.0)?;
+            self.debug_renderer.render()?;
+        }
 
-        self.debug_program.use_program()?;
-        Self::set_transform(&mut self.debug_program, zoom, -camera.x, -camera.y, 1.0, 0.0)?;
-        self.debug_renderer.render()?;
         self.sprite_program.use_program()?;
+        Self::set_gui_transform(&mut self.sprite_program, width, height, ui_scale)?;
+        if scene != state::Scene::Gameplay {
+            self.menu.render_text()?;
+        }
         self.debug_renderer.render_text()?;
+        if state.console.open {
+            self.console.render_text()?;
+        }
+
+        self.debug_program.use_program()?;
+        Self::set_gui_transform(&mut self.debug_program, width, height, ui_scale)?;
+        self.debug_renderer.render_graph()?;
 
         Ok(())
     }
 
 
-    fn set_transform(program: &mut rgl::Program, zoom: f32, x: f32, y: f32, scale: f32, angle: f32)
+    fn interpolate(previous: &VecDeque<Vec2>, current: &VecDeque<Vec2>, t: f32) -> VecDeque<Vec2> {
+        // Links were added or removed on the last step, so there is nothing to interpolate from.
+        if previous.len() != current.len() {
+            return current.clone();
+        }
+        previous.iter().copied().zip(current.iter().copied()).map(|(p, p2)| p + (p2 - p) * t).collect()
+    }
+
+
+    /// Converts a point in pixels from the top left corner of the screen to world coordinates.
+    pub fn screen_to_world(screen: &state::Screen, camera: &state::Camera, p: Vec2) -> Vec2 {
+        let (width, height) = (screen.width as f32, screen.height as f32);
+        let aspect = height / width;
+        let x = p.x / width * 2.0 - 1.0;
+        let y = 1.0 - p.y / height * 2.0;
+        camera.position + vec2(x / (aspect * camera.zoom), y / camera.zoom)
+    }
+
+
+    /// `aspect` is the height of the screen divided by its width.
+    fn set_transform(program: &mut rgl::Program, aspect: f32, zoom: f32, x: f32, y: f32, scale: f32, angle: f32)
         -> Result<(), rgl::GLError>
     {
-        let aspect = 9.0 / 16.0;
         let transform = rgl::Uniform::Matrix3x2([
             (angle.cos() * scale * aspect * zoom, -angle.sin() * scale * zoom),
             (angle.sin() * scale * aspect * zoom, angle.cos() * scale * zoom),
@@ -156,4 +361,65 @@ impl Renderer {
         program.set_uniform("transform", transform)?;
         Ok(())
     }
+
+
+    /// Lays out the GUI in pixels divided by `scale`, with the origin at the top left corner.
+    fn set_gui_transform(program: &mut rgl::Program, width: f32, height: f32, scale: f32) -> Result<(), rgl::GLError> {
+        Self::set_transform(program, height / width, 2.0 / height, -width * 0.5, height * 0.5, scale, 0.0)
+    }
+}
+
+
+impl ErrorScreen {
+    pub fn new() -> Result<ErrorScreen, Box<dyn Error>> {
+        rgl::set_blend_function(Some(rgl::BlendFunction(
+            rgl::BlendFactor::One,
+            rgl::BlendFactor::OneMinusSourceAlpha
+        )))?;
+        let mut program = Renderer::create_program(
+            include_str!("renderer/sprite.vert"),
+            include_str!("renderer/sprite.frag")
+        )?;
+        program.set_uniform("texture0", rgl::Uniform::Integer1(0))?;
+
+        let library = text::Library::new()?;
+        Ok(ErrorScreen {
+            program,
+            title_font: library.new_font("font/Roboto-Bold.ttf", 36)?,
+            font: library.new_font("font/Roboto-Bold.ttf", 18)?,
+            text: text::Text::new(),
+            title_text: text::Text::new()
+        })
+    }
+
+
+    pub fn render(&mut self, error: &GameError, width: i32, height: i32) -> Result<(), rgl::GLError> {
+        let mut p = vec2(40.0, -40.0);
+        let title = format!("{:?} error", error.kind());
+        self.title_text.add_text_rgb(&self.title_font, title.as_str(), p, 255, 128, 128);
+        p.y -= self.title_font.height() * 1.5;
+        let context = format!("While {}:", error.context());
+        self.text.add_text(&self.font, context.as_str(), p);
+        p.y -= self.font.height() * 1.5;
+        for line in error.error().lines() {
+            self.text.add_text_rgb(&self.font, line, p + vec2(20.0, 0.0), 255, 220, 96);
+            p.y -= self.font.height();
+        }
+        p.y -= self.font.height();
+        self.text.add_text_rgb(&self.font, "The error was written to the log file.", p, 191, 191, 191);
+        p.y -= self.font.height();
+        self.text.add_text_rgb(&self.font, "Press Escape to quit.", p, 191, 191, 191);
+        self.title_text.update(true)?;
+        self.text.update(true)?;
+
+        rgl::viewport(0, 0, width, height)?;
+        rgl::clear(0.2, 0.15, 0.3, 1.0)?;
+        self.program.use_program()?;
+        Renderer::set_gui_transform(&mut self.program, width as f32, height as f32, 1.0)?;
+        self.title_font.bind(0)?;
+        self.title_text.render()?;
+        self.font.bind(0)?;
+        self.text.render()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,50 @@
+use std::error::Error;
+
+use lib::rgl;
+use lib::math::{Vec2, vec2};
+
+use super::state;
+use super::text::{self, Font, Text};
+
+
+pub struct Console {
+    font: Font,
+    text: Text
+}
+
+
+impl Console {
+    pub fn new(library: &text::Library) -> Result<Console, Box<dyn Error>> {
+        Ok(Console {
+            font: library.new_font("font/Roboto-Bold.ttf", 18)?,
+            text: Text::new()
+        })
+    }
+
+
+    /// Lists the output above the command being typed, at the bottom left of the screen.
+    pub fn update(&mut self, console: &state::Console, screen: &state::Screen) -> Result<(), rgl::GLError> {
+        let mut p = vec2(10.0, (10.0 + self.font.height() - screen.height as f32).round());
+        let input = format!("> {}_", console.input);
+        self.add_text(&input, p, (255, 255, 255));
+        for line in console.lines.iter().rev() {
+            p.y += self.font.height();
+            self.add_text(line, p, (191, 191, 191));
+        }
+        self.text.update(true)?;
+        Ok(())
+    }
+
+
+    fn add_text(&mut self, string: &str, p: Vec2, (r, g, b): (u8, u8, u8)) {
+        self.text.add_text_rgb(&self.font, string, p + vec2(1.0, -1.0), 0, 0, 0);
+        self.text.add_text_rgb(&self.font, string, p, r, g, b);
+    }
+
+
+    pub fn render_text(&self) -> Result<(), rgl::GLError> {
+        self.font.bind(0)?;
+        self.text.render()?;
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use lib::rgl;
 use lib::math::{Vec2, vec2};
 
 use super::state;
+use super::controls::{Action, Controls};
 use super::text::{self, Font, Text};
 use super::vertex::DebugVertex;
 
@@ -11,23 +12,52 @@ use super::vertex::DebugVertex;
 pub struct Renderer {
     vertex_array: rgl::VertexArray,
     vertices: usize,
+    graph_vertex_array: rgl::VertexArray,
+    graph_vertices: usize,
     font: Font,
     text: Text
 }
 
 
+/// The names and graph colors of the profiler sections, in the order of `state::ProfileSection`.
+const PROFILE_SECTIONS: [(&str, (u8, u8, u8)); state::PROFILE_SECTIONS] = [
+    ("Events", (255, 128, 128)),
+    ("update_cat", (255, 200, 96)),
+    ("Physics step", (128, 255, 128)),
+    ("NoodleCat::update", (96, 200, 255)),
+    ("Vertices", (200, 128, 255)),
+    ("GL submit", (255, 255, 255))
+];
+
+/// The physics debug flags, which are toggled from the console.
+const PHYSICS_FLAGS: [(state::DebugPhysics, &str); 5] = [
+    (state::DebugPhysics::SHAPES, "Show shapes"),
+    (state::DebugPhysics::JOINTS, "Show joints"),
+    (state::DebugPhysics::AABBS, "Show AABBs"),
+    (state::DebugPhysics::TRANSFORMS, "Show transforms"),
+    (state::DebugPhysics::CONTACTS, "Show contacts")
+];
+
+/// Pixels per millisecond in the profiler graph.
+const GRAPH_SCALE: f32 = 4.0;
+
+
 impl Renderer {
     pub fn new(library: &text::Library) -> Result<Renderer, Box<dyn Error>> {
         Ok(Renderer {
             vertex_array: DebugVertex::create_array(&[])?,
             vertices: 0,
+            graph_vertex_array: DebugVertex::create_array(&[])?,
+            graph_vertices: 0,
             font: library.new_font("font/Roboto-Bold.ttf", 18)?,
             text: Text::new()
         })
     }
 
 
-    pub fn update(&mut self, info: &mut state::DebugInfo) -> Result<(), rgl::GLError> {
+    pub fn update(&mut self, info: &mut state::DebugInfo, controls: &Controls, screen: &state::Screen)
+        -> Result<(), rgl::GLError>
+    {
         let mut vertices: Vec<DebugVertex> = Vec::new();
 
         for shape in info.shapes.drain(..) {
@@ -50,49 +80,88 @@ impl Renderer {
         let fps = info.frames.len();
         let (r, g, b) = if info.skipped_steps { (255, 0, 0) } else { (0, 255, 0) };
         self.text.add_text_rgb(&self.font, format!("{}", fps).as_str(), p + vec2(40.0, 0.0), r, g, b);
-        if info.paused {
+        if info.rewinding {
+            self.text.add_text_rgb(&self.font, "Rewinding", p + vec2(80.0, 0.0), 128, 128, 255);
+        }
+        else if info.paused {
             self.text.add_text_rgb(&self.font, "Paused", p + vec2(80.0, 0.0), 255, 128, 128);
         }
         p.y -= self.font.height() * 1.5;
-        self.text.add_text(&self.font, "[0]: ", p);
+        // The hints show the keys that the actions are bound to in the settings.
+        let console = format!("[{}]: Console", controls.key_labels(Action::ToggleConsole));
+        self.text.add_text(&self.font, console.as_str(), p);
+        p.y -= self.font.height();
+        self.text.add_text(&self.font, "Time scale: ", p);
+        let (r, g, b) = if info.time_scale == 1.0 { (255, 255, 255) } else { (255, 255, 128) };
+        self.text.add_text_rgb(&self.font, &format!("{}x", info.time_scale), p + vec2(80.0, 0.0), r, g, b);
+        p.y -= self.font.height();
         let (r, g, b) = if info.show_physics { (191, 255, 191) } else { (191, 128, 128) };
-        self.text.add_text_rgb(&self.font, "Debug physics", p + vec2(30.0, 0.0), r, g, b);
+        self.text.add_text_rgb(&self.font, "Debug physics", p, r, g, b);
         p.y -= self.font.height();
         if info.show_physics {
-            let (shapes, joints, aabbs, transforms, contacts) = (
-                info.physics_flags.contains(state::DebugPhysics::SHAPES),
-                info.physics_flags.contains(state::DebugPhysics::JOINTS),
-                info.physics_flags.contains(state::DebugPhysics::AABBS),
-                info.physics_flags.contains(state::DebugPhysics::TRANSFORMS),
-                info.physics_flags.contains(state::DebugPhysics::CONTACTS)
-            );
-            self.text.add_text(&self.font, "[1]: ", p + vec2(10.0, 0.0));
-            let (r, g, b) = if shapes { (191, 255, 191) } else { (191, 128, 128) };
-            self.text.add_text_rgb(&self.font, "Show shapes", p + vec2(40.0, 0.0), r, g, b);
-            p.y -= self.font.height();
-            self.text.add_text(&self.font, "[2]: ", p + vec2(10.0, 0.0));
-            let (r, g, b) = if joints { (191, 255, 191) } else { (191, 128, 128) };
-            self.text.add_text_rgb(&self.font, "Show joints", p + vec2(40.0, 0.0), r, g, b);
-            p.y -= self.font.height();
-            self.text.add_text(&self.font, "[3]: ", p + vec2(10.0, 0.0));
-            let (r, g, b) = if aabbs { (191, 255, 191) } else { (191, 128, 128) };
-            self.text.add_text_rgb(&self.font, "Show AABBs", p + vec2(40.0, 0.0), r, g, b);
-            p.y -= self.font.height();
-            self.text.add_text(&self.font, "[4]: ", p + vec2(10.0, 0.0));
-            let (r, g, b) = if transforms { (191, 255, 191) } else { (191, 128, 128) };
-            self.text.add_text_rgb(&self.font, "Show transforms", p + vec2(40.0, 0.0), r, g, b);
-            p.y -= self.font.height();
-            self.text.add_text(&self.font, "[5]: ", p + vec2(10.0, 0.0));
-            let (r, g, b) = if contacts { (191, 255, 191) } else { (191, 128, 128) };
-            self.text.add_text_rgb(&self.font, "Show contacts", p + vec2(40.0, 0.0), r, g, b);
-            p.y -= self.font.height();
+            for (flag, name) in PHYSICS_FLAGS.iter() {
+                let (r, g, b) = if info.physics_flags.contains(*flag) { (191, 255, 191) } else { (191, 128, 128) };
+                self.text.add_text_rgb(&self.font, name, p + vec2(10.0, 0.0), r, g, b);
+                p.y -= self.font.height();
+            }
         }
+        let profiler = format!("[{}]: ", controls.key_labels(Action::ToggleProfiler));
+        self.text.add_text(&self.font, profiler.as_str(), p);
+        let (r, g, b) = if info.show_profiler { (191, 255, 191) } else { (191, 128, 128) };
+        let x = self.font.text_width(profiler.as_str());
+        self.text.add_text_rgb(&self.font, "Profiler", p + vec2(x, 0.0), r, g, b);
+        p.y -= self.font.height();
+        if let Some(error) = &info.reload_error {
+            for line in error.lines() {
+                self.text.add_text_rgb(&self.font, line, p, 255, 96, 96);
+                p.y -= self.font.height();
+            }
+        }
+        let mut graph_vertices: Vec<DebugVertex> = Vec::new();
+        if info.show_profiler {
+            self.update_profiler(&info.profile, screen, p, &mut graph_vertices);
+        }
+        self.graph_vertex_array.buffer.set_data(graph_vertices.as_slice(), rgl::BufferUsage::StreamDraw)?;
+        self.graph_vertices = graph_vertices.len();
         self.text.update(true)?;
 
         Ok(())
     }
 
 
+    /// Lists the average milliseconds per frame of each section, and draws a bar for each recent frame.
+    fn update_profiler(
+        &mut self,
+        profile: &state::Profile,
+        screen: &state::Screen,
+        mut p: Vec2,
+        vertices: &mut Vec<DebugVertex>
+    ) {
+        let frames = profile.frames.len().max(1) as f32;
+        for (i, (name, (r, g, b))) in PROFILE_SECTIONS.iter().copied().enumerate() {
+            let average = profile.frames.iter().map(|frame| frame[i]).sum::<f32>() / frames * 1000.0;
+            let line = format!("{}: {:.3} ms", name, average);
+            self.text.add_text_rgb(&self.font, line.as_str(), p + vec2(10.0, 0.0), r, g, b);
+            p.y -= self.font.height();
+        }
+
+        // The graph stacks the sections of each frame, with a line at the length of a frame at 60 FPS.
+        let bottom = 10.0 - screen.height as f32;
+        let target = bottom + 1000.0 / 60.0 * GRAPH_SCALE;
+        let right = 10.0 + state::PROFILE_FRAMES as f32;
+        Self::add_line(vertices, 10.0, target, right, target, 128, 128, 128, 255);
+        for (x, frame) in profile.frames.iter().enumerate() {
+            let x = 10.5 + x as f32;
+            let mut y = bottom;
+            for (seconds, (_, (r, g, b))) in frame.iter().copied().zip(PROFILE_SECTIONS.iter().copied()) {
+                let y2 = y + seconds * 1000.0 * GRAPH_SCALE;
+                Self::add_line(vertices, x, y, x, y2, r, g, b, 255);
+                y = y2;
+            }
+        }
+    }
+
+
     fn add_line(vertices: &mut Vec<DebugVertex>, x1: f32, y1: f32, x2: f32, y2: f32, r: u8, g: u8, b: u8, a: u8) {
         vertices.extend([
             DebugVertex::new(vec2(x1, y1), r, g, b, a),
@@ -119,6 +188,13 @@ impl Renderer {
     }
 
 
+    pub fn render_graph(&self) -> Result<(), rgl::GLError> {
+        self.graph_vertex_array.bind()?;
+        rgl::draw(rgl::DrawMode::Lines, 0, self.graph_vertices as _)?;
+        Ok(())
+    }
+
+
     pub fn render_text(&self) -> Result<(), rgl::GLError> {
         self.font.bind(0)?;
         self.text.render()?;
@@ -0,0 +1,69 @@
+use std::error::Error;
+
+use lib::rgl;
+use lib::math::{Vec2, vec2};
+
+use super::state;
+use super::ground::Ground;
+use super::text::{self, Font, Text};
+use super::vertex::Vertex;
+
+
+pub struct Editor {
+    font: Font,
+    text: Text,
+    vertex_array: rgl::VertexArray,
+    vertices: usize
+}
+
+
+impl Editor {
+    pub fn new(library: &text::Library) -> Result<Editor, Box<dyn Error>> {
+        Ok(Editor {
+            font: library.new_font("font/Roboto-Bold.ttf", 18)?,
+            text: Text::new(),
+            vertex_array: Vertex::create_array(&[], rgl::BufferUsage::StreamDraw)?,
+            vertices: 0
+        })
+    }
+
+
+    /// Marks the spawn and the tile under the cursor, and shows the glyph being placed at the top right of the screen.
+    pub fn update(&mut self, editor: &state::Editor, screen: &state::Screen) -> Result<(), rgl::GLError> {
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(12);
+        let (min, max) = (vec2(-0.5, -0.5), vec2(0.5, 0.5));
+        // Colors are premultiplied by alpha.
+        Ground::add_quad(&mut vertices, editor.spawn, min, max, (60, 150, 60, 150));
+        if let Some(p) = editor.cursor {
+            Ground::add_quad(&mut vertices, p, min, max, (100, 100, 100, 100));
+        }
+        self.vertex_array.buffer.set_data(vertices.as_slice(), rgl::BufferUsage::StreamDraw)?;
+        self.vertices = vertices.len();
+
+        let label = format!("Editor: '{}' {}", editor.glyph, editor.glyph_name);
+        let p = vec2((screen.width as f32 - self.font.text_width(&label) - 10.0).round(), -10.0);
+        self.add_text(&label, p);
+        self.text.update(true)?;
+        Ok(())
+    }
+
+
+    fn add_text(&mut self, string: &str, p: Vec2) {
+        self.text.add_text_rgb(&self.font, string, p + vec2(1.0, -1.0), 0, 0, 0);
+        self.text.add_text(&self.font, string, p);
+    }
+
+
+    pub fn render(&self) -> Result<(), rgl::GLError> {
+        self.vertex_array.bind()?;
+        rgl::draw(rgl::DrawMode::Triangles, 0, self.vertices as _)?;
+        Ok(())
+    }
+
+
+    pub fn render_text(&self) -> Result<(), rgl::GLError> {
+        self.font.bind(0)?;
+        self.text.render()?;
+        Ok(())
+    }
+}
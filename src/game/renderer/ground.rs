@@ -1,5 +1,5 @@
 use lib::rgl;
-use lib::math::vec2;
+use lib::math::{Vec2, vec2};
 
 use super::state;
 use super::vertex::Vertex;
@@ -26,17 +26,39 @@ impl Ground {
         }
         ground.dirty -= state::DirtyFlags::RENDER;
 
-        let mut vertices: Vec<Vertex> = Vec::with_capacity(ground.boxes.len() * 6);
+        let mut vertices: Vec<Vertex> = Vec::with_capacity((ground.boxes.len() + ground.tiles.len() + 1) * 6);
 
         for p in ground.boxes.iter().copied() {
-            vertices.extend([
-                Vertex::new(p + vec2(-0.5, 0.5), vec2(0.0, 0.0)),
-                Vertex::new(p + vec2(-0.5, -0.5), vec2(0.0, 1.0)),
-                Vertex::new(p + vec2(0.5, -0.5), vec2(1.0, 1.0)),
-                Vertex::new(p + vec2(-0.5, 0.5), vec2(0.0, 0.0)),
-                Vertex::new(p + vec2(0.5, -0.5), vec2(1.0, 1.0)),
-                Vertex::new(p + vec2(0.5, 0.5), vec2(1.0, 0.0))
-            ].into_iter());
+            Self::add_quad(&mut vertices, p, vec2(-0.5, -0.5), vec2(0.5, 0.5), (255, 255, 255, 255));
+        }
+
+        // The other tiles are tinted parts of the ground sprite.
+        for (p, tile) in ground.tiles.iter().copied() {
+            let (min, max, color) = match tile {
+                state::Tile::OneWay => (vec2(-0.5, 0.25), vec2(0.5, 0.5), (255, 200, 150, 255)),
+                state::Tile::Ice => (vec2(-0.5, -0.5), vec2(0.5, 0.5), (170, 220, 255, 255)),
+                state::Tile::Sticky => (vec2(-0.5, -0.5), vec2(0.5, 0.5), (150, 255, 120, 255)),
+                state::Tile::Spikes => {
+                    for i in 0..3 {
+                        let x = i as f32 / 3.0 - 0.5;
+                        let corners = [vec2(x, -0.5), vec2(x + 1.0 / 6.0, 0.0), vec2(x + 1.0 / 3.0, -0.5)];
+                        Self::add_triangle(&mut vertices, p, corners, (255, 110, 110, 255));
+                    }
+                    continue;
+                }
+                // Colors are premultiplied by alpha.
+                state::Tile::Water => (vec2(-0.5, -0.5), vec2(0.5, 0.5), (40, 80, 150, 150))
+            };
+            Self::add_quad(&mut vertices, p, min, max, color);
+        }
+
+        for p in ground.food.iter().copied() {
+            Self::add_quad(&mut vertices, p, vec2(-0.25, -0.25), vec2(0.25, 0.25), (255, 170, 60, 255));
+        }
+
+        // The exit is a tinted box.
+        if let Some(p) = ground.exit {
+            Self::add_quad(&mut vertices, p, vec2(-0.5, -0.5), vec2(0.5, 0.5), (255, 220, 96, 255));
         }
 
         self.vertex_array = Some(Vertex::create_array(vertices.as_slice(), rgl::BufferUsage::StaticDraw)?);
@@ -45,6 +67,23 @@ impl Ground {
     }
 
 
+    /// Adds the part of a tile at `p` from `min` to `max`, relative to its center.
+    pub fn add_quad(vertices: &mut Vec<Vertex>, p: Vec2, min: Vec2, max: Vec2, color: (u8, u8, u8, u8)) {
+        let (top_left, bottom_right) = (vec2(min.x, max.y), vec2(max.x, min.y));
+        Self::add_triangle(vertices, p, [top_left, min, bottom_right], color);
+        Self::add_triangle(vertices, p, [top_left, bottom_right, max], color);
+    }
+
+
+    /// Adds a triangle of a tile at `p`, with corners relative to its center and texture coordinates to match.
+    fn add_triangle(vertices: &mut Vec<Vertex>, p: Vec2, corners: [Vec2; 3], (r, g, b, a): (u8, u8, u8, u8)) {
+        vertices.extend(corners.iter().map(|corner| Vertex {
+            a,
+            ..Vertex::rgb(p + *corner, vec2(corner.x + 0.5, 0.5 - corner.y), r, g, b)
+        }));
+    }
+
+
     pub fn render(&self) -> Result<(), rgl::GLError> {
         if let Some(vertex_array) = &self.vertex_array {
             vertex_array.bind()?;
@@ -22,10 +22,19 @@ impl GUI {
     }
 
 
-    pub fn update(&mut self, gui: &state::GUI) -> Result<(), rgl::GLError> {
+    pub fn update(&mut self, gui: &state::GUI, screen: &state::Screen) -> Result<(), rgl::GLError> {
         let energy = format!("Calories: {}", gui.cat_energy);
-        self.text.add_text_rgb(&self.font, energy.as_str(), vec2(402.0, -101.0), 0, 0, 0);
-        self.text.add_text(&self.font, energy.as_str(), vec2(400.0, -100.0));
+        let p = vec2(((screen.width as f32 - self.font.text_width(energy.as_str())) * 0.5).round(), -100.0);
+        self.text.add_text_rgb(&self.font, energy.as_str(), p + vec2(2.0, -1.0), 0, 0, 0);
+        self.text.add_text(&self.font, energy.as_str(), p);
+        let time = match gui.best_time {
+            Some(best_time) => format!("Time: {:.1} (best {:.1})", gui.level_time, best_time),
+            None => format!("Time: {:.1}", gui.level_time)
+        };
+        let x = ((screen.width as f32 - self.font.text_width(time.as_str())) * 0.5).round();
+        let p = vec2(x, p.y - self.font.height());
+        self.text.add_text_rgb(&self.font, time.as_str(), p + vec2(2.0, -1.0), 0, 0, 0);
+        self.text.add_text(&self.font, time.as_str(), p);
         self.text.update(true)?;
         Ok(())
     }
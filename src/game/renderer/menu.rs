@@ -0,0 +1,63 @@
+use std::error::Error;
+
+use lib::rgl;
+use lib::math::{Vec2, vec2};
+
+use super::state;
+use super::text::{self, Font, Text};
+
+
+pub struct Menu {
+    title_font: Font,
+    title_text: Text,
+    font: Font,
+    text: Text
+}
+
+
+impl Menu {
+    pub fn new(library: &text::Library) -> Result<Menu, Box<dyn Error>> {
+        Ok(Menu {
+            title_font: library.new_font("font/Roboto-Bold.ttf", 72)?,
+            title_text: Text::new(),
+            font: library.new_font("font/Roboto-Bold.ttf", 36)?,
+            text: Text::new()
+        })
+    }
+
+
+    pub fn update(&mut self, menu: &state::Menu, screen: &state::Screen) -> Result<(), rgl::GLError> {
+        let center = screen.width as f32 * 0.5;
+        let mut y = -(screen.height as f32 * 0.3).round();
+
+        let p = vec2((center - self.title_font.text_width(menu.title) * 0.5).round(), y);
+        Self::add_text(&mut self.title_text, &self.title_font, menu.title, p, (255, 255, 255));
+        y -= self.title_font.height() * 1.5;
+
+        for (i, item) in menu.items.iter().enumerate() {
+            let color = if i == menu.selected { (255, 220, 96) } else { (191, 191, 191) };
+            let p = vec2((center - self.font.text_width(item) * 0.5).round(), y);
+            Self::add_text(&mut self.text, &self.font, item, p, color);
+            y -= self.font.height() * 1.25;
+        }
+
+        self.title_text.update(true)?;
+        self.text.update(true)?;
+        Ok(())
+    }
+
+
+    fn add_text(text: &mut Text, font: &Font, string: &str, p: Vec2, (r, g, b): (u8, u8, u8)) {
+        text.add_text_rgb(font, string, p + vec2(2.0, -1.0), 0, 0, 0);
+        text.add_text_rgb(font, string, p, r, g, b);
+    }
+
+
+    pub fn render_text(&self) -> Result<(), rgl::GLError> {
+        self.title_font.bind(0)?;
+        self.title_text.render()?;
+        self.font.bind(0)?;
+        self.text.render()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use lib::rgl;
 use lib::math::{Vec2, vec2};
 
@@ -24,9 +26,9 @@ impl NoodleCat {
     }
 
 
-    pub fn update(&mut self, cat: &state::Cat) -> Result<(), rgl::GLError> {
-        let path = &cat.path;
-        let tail = &cat.tail;
+    pub fn update(&mut self, cat: &state::Cat, path: &VecDeque<Vec2>, tail: &VecDeque<Vec2>)
+        -> Result<(), rgl::GLError>
+    {
         let mut vertices: Vec<Vertex> = Vec::with_capacity((path.len() + tail.len() + 11) * 6);
 
         let walk_phase = Vec2::from_angle(-cat.walk_phase * std::f32::consts::PI / 0.4);
@@ -0,0 +1,74 @@
+use lib::rgl;
+use lib::math::{Vec2, vec2, wrap_angle};
+
+use super::state;
+use super::vertex::Vertex;
+
+
+/// How many triangles a circle is drawn with.
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// Draws the props with the ground sprite, tinted by the color of their definition.
+pub struct Props {
+    vertex_array: rgl::VertexArray,
+    vertices: usize
+}
+
+
+impl Props {
+    pub fn new() -> Result<Props, rgl::GLError> {
+        Ok(Props {
+            vertex_array: Vertex::create_array(&[], rgl::BufferUsage::StreamDraw)?,
+            vertices: 0
+        })
+    }
+
+
+    pub fn update(&mut self, props: &[state::Prop], interpolation: f32) -> Result<(), rgl::GLError> {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        for prop in props {
+            let p = prop.previous_position + (prop.position - prop.previous_position) * interpolation;
+            let angle = prop.previous_angle + wrap_angle(prop.angle - prop.previous_angle) * interpolation;
+            let d = Vec2::from_angle(angle);
+            let (r, g, b) = prop.color;
+            // Texture coordinates are flipped vertically, so that the top of the sprite is up.
+            let vertex = |corner: Vec2, tex_coord: Vec2| Vertex::rgb(p + corner.rotated(d), tex_coord, r, g, b);
+            match prop.shape {
+                state::PropShape::Circle(radius) => {
+                    let segment_angle = std::f32::consts::PI * 2.0 / CIRCLE_SEGMENTS as f32;
+                    let corner = |i: usize| Vec2::from_angle(i as f32 * segment_angle);
+                    for i in 0..CIRCLE_SEGMENTS {
+                        let (c1, c2) = (corner(i), corner(i + 1));
+                        vertices.extend([
+                            vertex(vec2(0.0, 0.0), vec2(0.5, 0.5)),
+                            vertex(c1 * radius, vec2(0.5 + c1.x * 0.5, 0.5 - c1.y * 0.5)),
+                            vertex(c2 * radius, vec2(0.5 + c2.x * 0.5, 0.5 - c2.y * 0.5))
+                        ].iter().copied());
+                    }
+                }
+                state::PropShape::Box(width, height) => {
+                    let (x, y) = (width * 0.5, height * 0.5);
+                    vertices.extend([
+                        vertex(vec2(-x, y), vec2(0.0, 0.0)),
+                        vertex(vec2(-x, -y), vec2(0.0, 1.0)),
+                        vertex(vec2(x, -y), vec2(1.0, 1.0)),
+                        vertex(vec2(-x, y), vec2(0.0, 0.0)),
+                        vertex(vec2(x, -y), vec2(1.0, 1.0)),
+                        vertex(vec2(x, y), vec2(1.0, 0.0))
+                    ].iter().copied());
+                }
+            }
+        }
+
+        self.vertex_array.buffer.set_data(vertices.as_slice(), rgl::BufferUsage::StreamDraw)?;
+        self.vertices = vertices.len();
+        Ok(())
+    }
+
+
+    pub fn render(&self) -> Result<(), rgl::GLError> {
+        self.vertex_array.bind()?;
+        rgl::draw(rgl::DrawMode::Triangles, 0, self.vertices as _)?;
+        Ok(())
+    }
+}
@@ -172,6 +172,22 @@ impl Font {
     pub fn height(&self) -> f32 {
         self.height
     }
+
+
+    /// The horizontal advance of a line of text, for aligning it.
+    pub fn text_width(&self, text: &str) -> f32 {
+        self.shape(text).get_glyph_positions().iter().map(|glyph_p| (glyph_p.x_advance / 64) as f32).sum()
+    }
+
+
+    fn shape(&self, text: &str) -> hb::GlyphBuffer {
+        let buffer = hb::UnicodeBuffer::new()
+            .add_str(text)
+            .set_direction(hb::Direction::Ltr)
+            .set_script("Latn".parse().unwrap())
+            .set_language("en".parse().unwrap());
+        hb::shape(&self.font, buffer, &[])
+    }
 }
 
 
@@ -204,18 +220,20 @@ impl Text {
     pub fn add_text_rgb(&mut self, font: &Font, text: &str, mut position: Vec2, r: u8, g: u8, b: u8) {
         self.vertex_data.reserve(text.len() * 6);
 
-        let buffer = hb::UnicodeBuffer::new()
-            .add_str(text)
-            .set_direction(hb::Direction::Ltr)
-            .set_script("Latn".parse().unwrap())
-            .set_language("en".parse().unwrap());
-        let glyphs = hb::shape(&font.font, buffer, &[]);
+        let glyphs = font.shape(text);
         let positions = glyphs.get_glyph_positions();
         let infos = glyphs.get_glyph_infos();
         let glyph_iter = positions.iter().zip(infos);
 
         for (glyph_p, info) in glyph_iter {
-            let glyph = &font.glyphs[&info.codepoint];
+            // Only printable ASCII is in the atlas, so other characters are left out as if they were spaces.
+            let glyph = match font.glyphs.get(&info.codepoint) {
+                Some(glyph) => glyph,
+                None => {
+                    position += vec2((glyph_p.x_advance / 64) as _, (glyph_p.y_advance / 64) as _);
+                    continue;
+                }
+            };
             if glyph.size == vec2(1.0, -1.0) {
                 position += vec2((glyph_p.x_advance / 64) as _, (glyph_p.y_advance / 64) as _);
                 continue;
@@ -0,0 +1,183 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    time,
+    io::{self, Write},
+    collections::VecDeque
+};
+
+use lib::math::{Vec2, vec2};
+
+use super::{Event, key};
+
+
+pub struct ReplayError {
+    error: String
+}
+
+/// A change to the body dragged with the mouse, in world coordinates.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Drag {
+    Start(Vec2),
+    Move(Vec2),
+    End
+}
+
+/// Writes every input event together with the index of the step it was applied on.
+///
+/// Each line is a step index followed by `Key <action> <key>`, `Button <action> <button>`, `Stick <x> <y>`,
+/// `Char <code>` or `Drag Start|Move <x> <y>` and `Drag End`.
+pub struct Recorder {
+    file: io::LineWriter<fs::File>
+}
+
+/// Feeds recorded input events back on the steps they were recorded on.
+pub struct Player {
+    events: VecDeque<(u64, Event)>,
+    drags: VecDeque<(u64, Drag)>
+}
+
+
+impl Error for ReplayError {}
+
+
+impl fmt::Debug for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Recorder {
+    pub fn new(file: &str) -> Result<Recorder, Box<dyn Error>> {
+        Ok(Recorder { file: io::LineWriter::new(fs::File::create(file)?) })
+    }
+
+
+    pub fn record(&mut self, step: u64, event: &Event) -> Result<(), Box<dyn Error>> {
+        match event {
+            Event::Close | Event::Resize(..) | Event::Fullscreen(..) | Event::WindowSize(..) => {}
+            // Where the mouse points depends on the camera, which isn't part of the simulation.
+            Event::MouseButton(..) | Event::CursorPos(..) | Event::CursorLeft => {}
+            Event::Key(action, key, _) => writeln!(self.file, "{} Key {:?} {:?}", step, action, key)?,
+            Event::GamepadButton(action, button, _) => {
+                writeln!(self.file, "{} Button {:?} {:?}", step, action, button)?;
+            }
+            Event::GamepadStick(stick, _) => writeln!(self.file, "{} Stick {} {}", step, stick.x, stick.y)?,
+            // Characters are written as numbers, since they may be whitespace.
+            Event::Char(c, _) => writeln!(self.file, "{} Char {}", step, *c as u32)?
+        }
+        Ok(())
+    }
+
+
+    /// Writes a drag, which depends on the camera and so is recorded where it lands in the world instead of as mouse
+    /// events.
+    pub fn record_drag(&mut self, step: u64, drag: Drag) -> Result<(), Box<dyn Error>> {
+        match drag {
+            Drag::Start(p) => writeln!(self.file, "{} Drag Start {} {}", step, p.x, p.y)?,
+            Drag::Move(p) => writeln!(self.file, "{} Drag Move {} {}", step, p.x, p.y)?,
+            Drag::End => writeln!(self.file, "{} Drag End", step)?
+        }
+        Ok(())
+    }
+}
+
+
+impl Player {
+    pub fn new(file: &str) -> Result<Player, Box<dyn Error>> {
+        let mut events = VecDeque::new();
+        let mut drags = VecDeque::new();
+        for (n, line) in fs::read_to_string(file)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(drag) = Self::parse_drag(line) {
+                drags.push_back(drag);
+                continue;
+            }
+            match Self::parse_event(line) {
+                Some(event) => events.push_back(event),
+                None => {
+                    return Err(ReplayError { error: format!("{}:{}: invalid event", file, n + 1) }.into());
+                }
+            }
+        }
+        Ok(Player { events, drags })
+    }
+
+
+    fn parse_drag(line: &str) -> Option<(u64, Drag)> {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let step = fields.first()?.parse().ok()?;
+        let drag = match fields[1..] {
+            ["Drag", "Start", x, y] => Drag::Start(vec2(x.parse().ok()?, y.parse().ok()?)),
+            ["Drag", "Move", x, y] => Drag::Move(vec2(x.parse().ok()?, y.parse().ok()?)),
+            ["Drag", "End"] => Drag::End,
+            _ => return None
+        };
+        Some((step, drag))
+    }
+
+
+    fn parse_event(line: &str) -> Option<(u64, Event)> {
+        // Recorded events are applied by step, so their time doesn't matter.
+        let time = time::Instant::now();
+        let mut fields = line.split_whitespace();
+        let step = fields.next()?.parse().ok()?;
+        let event = match fields.next()? {
+            "Key" => {
+                let action = key::action_from_name(fields.next()?)?;
+                Event::Key(action, key::from_name(fields.next()?)?, time)
+            }
+            "Button" => {
+                let action = key::action_from_name(fields.next()?)?;
+                Event::GamepadButton(action, key::gamepad_button_from_name(fields.next()?)?, time)
+            }
+            "Stick" => {
+                let x = fields.next()?.parse().ok()?;
+                let y = fields.next()?.parse().ok()?;
+                Event::GamepadStick(vec2(x, y), time)
+            }
+            "Char" => Event::Char(std::char::from_u32(fields.next()?.parse().ok()?)?, time),
+            _ => return None
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+        Some((step, event))
+    }
+
+
+    /// Returns the next event recorded on the given step, if any is left.
+    pub fn next_event(&mut self, step: u64) -> Option<Event> {
+        match self.events.front() {
+            Some((event_step, _)) if *event_step <= step => {
+                self.events.pop_front().map(|(_, event)| event)
+            }
+            _ => None
+        }
+    }
+
+
+    /// Returns the drag recorded on the given step, if any.
+    pub fn next_drag(&mut self, step: u64) -> Option<Drag> {
+        match self.drags.front() {
+            Some((drag_step, _)) if *drag_step <= step => self.drags.pop_front().map(|(_, drag)| drag),
+            _ => None
+        }
+    }
+
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty() && self.drags.is_empty()
+    }
+}
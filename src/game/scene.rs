@@ -0,0 +1,127 @@
+use super::state::{self, Scene};
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Item {
+    Start,
+    /// Starts the last unlocked level.
+    Continue,
+    Resume,
+    NextLevel,
+    Restart,
+    Title,
+    Quit
+}
+
+/// The title, items and the item chosen by going back of a scene's menu.
+struct MenuLayout {
+    title: &'static str,
+    items: &'static [(Item, &'static str)],
+    back: Option<Item>
+}
+
+
+fn layout(scene: Scene) -> MenuLayout {
+    match scene {
+        Scene::Title => MenuLayout {
+            title: "CAT NOODLE!",
+            items: &[(Item::Continue, "Continue"), (Item::Start, "Start"), (Item::Quit, "Quit")],
+            back: Some(Item::Quit)
+        },
+        Scene::Gameplay => MenuLayout {
+            title: "",
+            items: &[],
+            back: None
+        },
+        Scene::PauseMenu => MenuLayout {
+            title: "Paused",
+            items: &[(Item::Resume, "Resume"), (Item::Restart, "Restart level"), (Item::Title, "Quit to title")],
+            back: Some(Item::Resume)
+        },
+        Scene::LevelComplete => MenuLayout {
+            title: "Level complete!",
+            items: &[(Item::NextLevel, "Next level"), (Item::Restart, "Play again"), (Item::Title, "Quit to title")],
+            back: Some(Item::Title)
+        },
+        Scene::GameOver => MenuLayout {
+            title: "Game over",
+            items: &[(Item::Restart, "Try again"), (Item::Title, "Quit to title")],
+            back: Some(Item::Title)
+        }
+    }
+}
+
+
+pub fn active(state: &state::State) -> Scene {
+    state.scenes.last().copied().unwrap_or(Scene::Gameplay)
+}
+
+
+/// Opens a scene on top of the active one.
+pub fn push(state: &mut state::State, scene: Scene) {
+    state.scenes.push(scene);
+    open_menu(state);
+}
+
+
+/// Closes the active scene and returns to the one below it.
+pub fn pop(state: &mut state::State) {
+    state.scenes.pop();
+    open_menu(state);
+}
+
+
+/// Replaces all scenes with a single one.
+pub fn set(state: &mut state::State, scene: Scene) {
+    state.scenes.clear();
+    push(state, scene);
+}
+
+
+fn open_menu(state: &mut state::State) {
+    let layout = layout(active(state));
+    state.menu = state::Menu {
+        title: layout.title,
+        items: layout.items.iter().map(|(_, name)| *name).collect(),
+        selected: 0
+    };
+    // Menu input from the previous scene shouldn't carry over, and neither should gameplay presses, since buttons like
+    // A both confirm and turn.
+    let input = &mut state.input;
+    input.menu = false;
+    input.confirm = false;
+    input.menu_up = false;
+    input.menu_down = false;
+    input.turn = false;
+    input.fly = false;
+    input.toggle_pause = false;
+    input.step = false;
+}
+
+
+/// Moves the selection of the active menu, and returns the item that was chosen, if any.
+pub fn update_menu(state: &mut state::State) -> Option<Item> {
+    let layout = layout(active(state));
+    let input = &mut state.input;
+    let menu = &mut state.menu;
+    if layout.items.is_empty() {
+        return None;
+    }
+    if input.menu_up {
+        input.menu_up = false;
+        menu.selected = (menu.selected + layout.items.len() - 1) % layout.items.len();
+    }
+    if input.menu_down {
+        input.menu_down = false;
+        menu.selected = (menu.selected + 1) % layout.items.len();
+    }
+    if input.confirm {
+        input.confirm = false;
+        return Some(layout.items[menu.selected].0);
+    }
+    if input.menu {
+        input.menu = false;
+        return layout.back;
+    }
+    None
+}
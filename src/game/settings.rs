@@ -0,0 +1,267 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    io::{self, Write},
+    env,
+    path::{Path, PathBuf}
+};
+
+use super::state::{DebugPhysics, Steering};
+use super::controls::{self, Action, Controls};
+
+
+pub struct SettingsError {
+    error: String
+}
+
+/// User settings that can change while the game is running, and are saved to the settings file on exit.
+///
+/// The settings file has one `name = value` line per setting, e.g. `zoom = 1.5`. Key bindings use the names of
+/// actions, e.g. `turn = Z, Space, ButtonA`, and a line replaces all default bindings of its action. Lines starting
+/// with `#` are comments.
+pub struct Settings {
+    pub vsync: bool,
+    pub fullscreen: bool,
+    /// The size of the window when it isn't fullscreen.
+    pub window_size: (u32, u32),
+    /// The physics debug shapes shown when the physics overlay is turned on.
+    pub debug_physics: DebugPhysics,
+    /// How far the camera is zoomed in, relative to the default.
+    pub zoom: f32,
+    /// The size of text and menus, relative to the default.
+    pub ui_scale: f32,
+    /// How quickly the cat turns and speeds up or slows down. The turn rate is in degrees per second in the file.
+    pub steering: Steering,
+    pub controls: Controls
+}
+
+
+pub const ZOOM_RANGE: (f32, f32) = (0.25, 4.0);
+pub const UI_SCALE_RANGE: (f32, f32) = (0.5, 3.0);
+pub const TURN_RATE_RANGE: (f32, f32) = (45.0, 3600.0);
+pub const ACCELERATION_RANGE: (f32, f32) = (0.5, 100.0);
+
+/// The names of the physics debug flags, as used by the settings file and the console.
+pub const DEBUG_PHYSICS: [(DebugPhysics, &str); 5] = [
+    (DebugPhysics::SHAPES, "shapes"),
+    (DebugPhysics::JOINTS, "joints"),
+    (DebugPhysics::AABBS, "aabbs"),
+    (DebugPhysics::TRANSFORMS, "transforms"),
+    (DebugPhysics::CONTACTS, "contacts")
+];
+
+
+impl Error for SettingsError {}
+
+
+impl fmt::Debug for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            vsync: true,
+            fullscreen: false,
+            window_size: (1280, 720),
+            debug_physics: DebugPhysics::all(),
+            zoom: 1.0,
+            ui_scale: 1.0,
+            steering: Steering {
+                turn_rate: std::f32::consts::PI * 4.0,
+                acceleration: 6.0,
+                deceleration: 10.0
+            },
+            controls: Controls::new()
+        }
+    }
+}
+
+
+impl Settings {
+    /// Loads the settings file next to the executable, or the default settings if there is none.
+    ///
+    /// Without a settings file, the key bindings of an older `controls.txt` are imported instead, and saved to the
+    /// settings file on exit.
+    pub fn load() -> Result<Settings, Box<dyn Error>> {
+        let file = Self::file()?;
+        let controls_file = file.with_file_name("controls.txt");
+        match (Self::read(&file)?, Self::read(&controls_file)?) {
+            (Some(source), controls) => {
+                if controls.is_some() {
+                    warn!("ignoring {}, since its bindings are in {}", controls_file.display(), file.display());
+                }
+                Self::parse(&file, &source)
+            }
+            (None, Some(source)) => {
+                info!("importing the key bindings of {}", controls_file.display());
+                Self::parse(&controls_file, &source)
+            }
+            (None, None) => Ok(Self::default())
+        }
+    }
+
+
+    /// Reads a file, or returns `None` if it doesn't exist.
+    fn read(file: &Path) -> Result<Option<String>, io::Error> {
+        match fs::read_to_string(file) {
+            Ok(source) => Ok(Some(source)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error)
+        }
+    }
+
+
+    fn parse(file: &Path, source: &str) -> Result<Settings, Box<dyn Error>> {
+        let mut settings = Self::default();
+        for (n, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |error: &dyn fmt::Display| {
+                SettingsError { error: format!("{}:{}: {}", file.display(), n + 1, error) }
+            };
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = parts.next().ok_or_else(|| error(&"expected '='"))?;
+            // Settings and actions of older versions are dropped, so that the file still loads and is rewritten.
+            if settings.get(name).is_none() {
+                warn!("{}:{}: ignoring unknown setting '{}'", file.display(), n + 1, name);
+                continue;
+            }
+            settings.set(name, value.trim()).map_err(|e| error(&e))?;
+        }
+        Ok(settings)
+    }
+
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        let mut file = io::BufWriter::new(fs::File::create(Self::file()?)?);
+        writeln!(file, "# Written by the game on exit.")?;
+        let names = [
+            "vsync", "window", "window_size", "debug_physics", "zoom", "ui_scale",
+            "turn_rate", "acceleration", "deceleration"
+        ];
+        for name in names.iter() {
+            writeln!(file, "{} = {}", name, self.get(name).unwrap())?;
+        }
+        writeln!(file)?;
+        writeln!(file, "# Key bindings")?;
+        for (action, name) in controls::ACTIONS.iter() {
+            writeln!(file, "{} = {}", name, self.controls.bindings(*action))?;
+        }
+        file.flush()
+    }
+
+
+    fn file() -> Result<PathBuf, io::Error> {
+        Ok(env::current_exe()?.with_file_name("settings.txt"))
+    }
+
+
+    /// Returns a setting or the bindings of an action in the format of the settings file.
+    pub fn get(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "vsync" => String::from(if self.vsync { "on" } else { "off" }),
+            "window" => String::from(if self.fullscreen { "fullscreen" } else { "windowed" }),
+            "window_size" => format!("{}x{}", self.window_size.0, self.window_size.1),
+            "debug_physics" => DEBUG_PHYSICS.iter()
+                .filter(|(flag, _)| self.debug_physics.contains(*flag))
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            "zoom" => self.zoom.to_string(),
+            "ui_scale" => self.ui_scale.to_string(),
+            "turn_rate" => self.steering.turn_rate.to_degrees().to_string(),
+            "acceleration" => self.steering.acceleration.to_string(),
+            "deceleration" => self.steering.deceleration.to_string(),
+            _ => self.controls.bindings(Action::from_name(name)?)
+        })
+    }
+
+
+    /// Changes a setting or the bindings of an action from a value in the format of the settings file.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SettingsError> {
+        let invalid = |expected: &str| {
+            SettingsError { error: format!("{} expects {}, got '{}'", name, expected, value) }
+        };
+        match name {
+            "vsync" => {
+                self.vsync = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid("on or off"))
+                };
+            }
+            "window" => {
+                self.fullscreen = match value {
+                    "fullscreen" => true,
+                    "windowed" => false,
+                    _ => return Err(invalid("fullscreen or windowed"))
+                };
+            }
+            "window_size" => {
+                let mut parts = value.splitn(2, 'x').map(str::parse);
+                self.window_size = match (parts.next(), parts.next()) {
+                    (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => (width, height),
+                    _ => return Err(invalid("a size like 1280x720"))
+                };
+            }
+            "debug_physics" => {
+                let mut flags = DebugPhysics::empty();
+                for flag in value.split(',').map(str::trim).filter(|flag| !flag.is_empty()) {
+                    let expected = "a list of shapes, joints, aabbs, transforms and contacts";
+                    flags |= DEBUG_PHYSICS.iter()
+                        .find(|(_, name)| *name == flag)
+                        .ok_or_else(|| invalid(expected))?.0;
+                }
+                self.debug_physics = flags;
+            }
+            "zoom" => self.zoom = parse_range(value, ZOOM_RANGE).ok_or_else(|| invalid(&range(ZOOM_RANGE)))?,
+            "ui_scale" => {
+                self.ui_scale = parse_range(value, UI_SCALE_RANGE).ok_or_else(|| invalid(&range(UI_SCALE_RANGE)))?;
+            }
+            "turn_rate" => {
+                let turn_rate = parse_range(value, TURN_RATE_RANGE).ok_or_else(|| invalid(&range(TURN_RATE_RANGE)))?;
+                self.steering.turn_rate = turn_rate.to_radians();
+            }
+            "acceleration" | "deceleration" => {
+                let rate = parse_range(value, ACCELERATION_RANGE).ok_or_else(|| invalid(&range(ACCELERATION_RANGE)))?;
+                if name == "acceleration" {
+                    self.steering.acceleration = rate;
+                }
+                else {
+                    self.steering.deceleration = rate;
+                }
+            }
+            _ => {
+                let action = Action::from_name(name)
+                    .ok_or_else(|| SettingsError { error: format!("unknown setting '{}'", name) })?;
+                self.controls.set_bindings(action, value).map_err(|error| SettingsError { error: error.to_string() })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+fn parse_range(value: &str, (min, max): (f32, f32)) -> Option<f32> {
+    value.parse().ok().filter(|value| *value >= min && *value <= max)
+}
+
+
+fn range((min, max): (f32, f32)) -> String {
+    format!("a number from {} to {}", min, max)
+}
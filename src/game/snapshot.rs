@@ -0,0 +1,289 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    env,
+    str::FromStr,
+    io::{self, Write},
+    path::PathBuf,
+    rc::Rc
+};
+
+use lib::math::{Vec2, vec2};
+
+use super::{state, physics, level};
+
+
+pub struct SnapshotError {
+    error: String
+}
+
+/// The parts of the game state that change while playing, along with the matching physics state.
+#[derive(Clone)]
+pub struct Snapshot {
+    direction: Option<f32>,
+    speed: f32,
+    turning: bool,
+    extending: bool,
+    contracting: bool,
+    flying: bool,
+    walk_phase: f32,
+    energy: u32,
+    /// Food comes back when rewinding to before it was eaten. It rarely changes, so it's shared with the previous
+    /// snapshot while it doesn't.
+    food: Rc<Vec<Vec2>>,
+    physics: physics::Snapshot
+}
+
+
+impl Error for SnapshotError {}
+
+
+impl fmt::Debug for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Snapshot {
+    /// `previous` is the snapshot taken before, if any, that unchanged parts are shared with.
+    pub fn new(state: &state::State, physics: &physics::World, previous: Option<&Snapshot>) -> Snapshot {
+        let cat = &state.cat;
+        let food = match previous {
+            Some(previous) if *previous.food == state.ground.food => Rc::clone(&previous.food),
+            _ => Rc::new(state.ground.food.clone())
+        };
+        Snapshot {
+            direction: cat.direction,
+            speed: cat.speed,
+            turning: cat.turning,
+            extending: cat.extending,
+            contracting: cat.contracting,
+            flying: cat.flying,
+            walk_phase: cat.walk_phase,
+            energy: cat.energy,
+            food,
+            physics: physics.snapshot()
+        }
+    }
+
+
+    pub fn restore(&self, state: &mut state::State, physics: &mut physics::World) {
+        state.cat.tail.resize(self.physics.cat.tail_links.len(), vec2(0.0, 0.0));
+        if state.ground.food != *self.food {
+            state.ground.food.clone_from(&self.food);
+            state.ground.dirty = state::DirtyFlags::all();
+        }
+        physics.restore(state, &self.physics);
+
+        let cat = &mut state.cat;
+        cat.direction = self.direction;
+        cat.speed = self.speed;
+        cat.turning = self.turning;
+        cat.extending = self.extending;
+        cat.contracting = self.contracting;
+        cat.flying = self.flying;
+        cat.walk_phase = self.walk_phase;
+        cat.energy = self.energy;
+    }
+}
+
+
+fn file() -> Result<PathBuf, io::Error> {
+    Ok(env::current_exe()?.with_file_name("snapshot.txt"))
+}
+
+
+/// Writes the ground and a snapshot of the game to the snapshot file next to the executable.
+///
+/// Each line is a record name followed by its values, e.g. `box <x> <y>` or
+/// `link <x> <y> <angle> <velocity x> <velocity y> <angular velocity>`. Missing optional values are written as `-`.
+pub fn save(state: &state::State, physics: &physics::World) -> Result<(), Box<dyn Error>> {
+    let snapshot = Snapshot::new(state, physics, None);
+    let mut file = io::BufWriter::new(fs::File::create(file()?)?);
+    writeln!(
+        file, "cat {} {} {} {} {} {} {} {}",
+        format_option(snapshot.direction), snapshot.speed,
+        snapshot.turning, snapshot.extending, snapshot.contracting, snapshot.flying,
+        snapshot.walk_phase, snapshot.energy
+    )?;
+    for p in state.ground.boxes.iter() {
+        writeln!(file, "box {} {}", p.x, p.y)?;
+    }
+    for (p, tile) in state.ground.tiles.iter() {
+        let name = level::TILES.iter().find(|(other, _, _)| other == tile).unwrap().2;
+        writeln!(file, "tile {} {} {}", name, p.x, p.y)?;
+    }
+    for p in snapshot.food.iter() {
+        writeln!(file, "food {} {}", p.x, p.y)?;
+    }
+    let cat = &snapshot.physics.cat;
+    writeln!(
+        file, "noodle {} {} {} {}",
+        cat.walk_length, cat.extend_phase, cat.contracting, cat.contract_phase
+    )?;
+    if let (Some(grabbed), Some(d)) = (cat.grabbed, cat.grab_d) {
+        writeln!(file, "grab {} {} {}", grabbed, d.x, d.y)?;
+    }
+    let bodies = [("link", &cat.links), ("tail", &cat.tail_links), ("prop", &snapshot.physics.props)];
+    for (name, bodies) in bodies.iter() {
+        for body in bodies.iter() {
+            writeln!(
+                file, "{} {} {} {} {} {} {}",
+                name, body.position.x, body.position.y, body.angle,
+                body.linear_velocity.x, body.linear_velocity.y, body.angular_velocity
+            )?;
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+
+/// Restores the ground and the game from the snapshot file.
+pub fn load(state: &mut state::State, physics: &mut physics::World) -> Result<(), Box<dyn Error>> {
+    let file = file()?;
+    let source = fs::read_to_string(&file)?;
+
+    let mut snapshot = Snapshot::new(state, physics, None);
+    let mut boxes = Vec::new();
+    let mut tiles = Vec::new();
+    let mut food = Vec::new();
+    let mut links = Vec::new();
+    let mut tail_links = Vec::new();
+    let mut props = Vec::new();
+    let mut grab = None;
+    for (n, line) in source.lines().enumerate() {
+        let error = |error: &str| SnapshotError { error: format!("{}:{}: {}", file.display(), n + 1, error) };
+        let mut values = line.split_whitespace();
+        let name = match values.next() {
+            Some(name) => name,
+            None => continue
+        };
+        let values: Vec<_> = values.collect();
+        let expect = |count: usize| if values.len() == count {
+            Ok(())
+        }
+        else {
+            Err(error(&format!("expected {} values", count)))
+        };
+        let invalid = || error("invalid value");
+        match name {
+            "cat" => {
+                expect(8)?;
+                snapshot.direction = parse_option(values[0]).ok_or_else(invalid)?;
+                snapshot.speed = parse(&values, 1).ok_or_else(invalid)?;
+                snapshot.turning = parse(&values, 2).ok_or_else(invalid)?;
+                snapshot.extending = parse(&values, 3).ok_or_else(invalid)?;
+                snapshot.contracting = parse(&values, 4).ok_or_else(invalid)?;
+                snapshot.flying = parse(&values, 5).ok_or_else(invalid)?;
+                snapshot.walk_phase = parse(&values, 6).ok_or_else(invalid)?;
+                snapshot.energy = parse(&values, 7).ok_or_else(invalid)?;
+            }
+            "box" => {
+                expect(2)?;
+                boxes.push(parse_vec2(&values, 0).ok_or_else(invalid)?);
+            }
+            "tile" => {
+                expect(3)?;
+                let tile = level::TILES.iter().find(|(_, _, name)| *name == values[0]).ok_or_else(invalid)?.0;
+                tiles.push((parse_vec2(&values, 1).ok_or_else(invalid)?, tile));
+            }
+            "food" => {
+                expect(2)?;
+                food.push(parse_vec2(&values, 0).ok_or_else(invalid)?);
+            }
+            "noodle" => {
+                expect(4)?;
+                let cat = &mut snapshot.physics.cat;
+                cat.walk_length = parse(&values, 0).ok_or_else(invalid)?;
+                cat.extend_phase = parse(&values, 1).ok_or_else(invalid)?;
+                cat.contracting = parse(&values, 2).ok_or_else(invalid)?;
+                cat.contract_phase = parse(&values, 3).ok_or_else(invalid)?;
+            }
+            "grab" => {
+                expect(3)?;
+                grab = Some((
+                    parse(&values, 0).ok_or_else(invalid)?,
+                    parse_vec2(&values, 1).ok_or_else(invalid)?
+                ));
+            }
+            "link" | "tail" | "prop" => {
+                expect(6)?;
+                let body = physics::BodySnapshot {
+                    position: parse_vec2(&values, 0).ok_or_else(invalid)?,
+                    angle: parse(&values, 2).ok_or_else(invalid)?,
+                    linear_velocity: parse_vec2(&values, 3).ok_or_else(invalid)?,
+                    angular_velocity: parse(&values, 5).ok_or_else(invalid)?
+                };
+                match name {
+                    "link" => links.push(body),
+                    "tail" => tail_links.push(body),
+                    _ => props.push(body)
+                }
+            }
+            _ => return Err(error(&format!("unknown record '{}'", name)).into())
+        }
+    }
+    // The cat needs a head, a neck and a butt, and something to attach the tail to.
+    if links.len() < 3 || tail_links.is_empty() {
+        return Err(SnapshotError { error: format!("{}: the cat is too short", file.display()) }.into());
+    }
+
+    let cat = &mut snapshot.physics.cat;
+    cat.links = links;
+    cat.tail_links = tail_links;
+    cat.grabbed = grab.map(|(grabbed, _)| grabbed);
+    cat.grab_d = grab.map(|(_, d)| d);
+    snapshot.physics.props = props;
+    snapshot.food = Rc::new(food);
+
+    state.ground.boxes = boxes;
+    state.ground.tiles = tiles;
+    state.ground.dirty = state::DirtyFlags::all();
+    snapshot.restore(state, physics);
+
+    // Jumping to another state shouldn't be interpolated.
+    let cat = &mut state.cat;
+    cat.previous_path.clone_from(&cat.path);
+    cat.previous_tail.clone_from(&cat.tail);
+    for prop in state.props.iter_mut() {
+        prop.previous_position = prop.position;
+        prop.previous_angle = prop.angle;
+    }
+    Ok(())
+}
+
+
+fn format_option(value: Option<f32>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "-".to_string()
+    }
+}
+
+
+fn parse<T: FromStr>(values: &[&str], index: usize) -> Option<T> {
+    values[index].parse().ok()
+}
+
+
+fn parse_vec2(values: &[&str], index: usize) -> Option<Vec2> {
+    Some(vec2(parse(values, index)?, parse(values, index + 1)?))
+}
+
+
+fn parse_option<T: FromStr>(value: &str) -> Option<Option<T>> {
+    match value {
+        "-" => Some(None),
+        _ => value.parse().ok().map(Some)
+    }
+}
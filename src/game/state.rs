@@ -16,18 +16,38 @@ pub struct Input {
     pub right: bool,
     pub up: bool,
     pub down: bool,
+    pub stick: Option<Vec2>,
     pub turn: bool,
     pub extend: bool,
     pub contract: bool,
     pub fly: bool,
     pub toggle_pause: bool,
     pub step: bool,
-    pub toggle_debug_physics: bool,
-    pub toggle_debug_physics_shapes: bool,
-    pub toggle_debug_physics_joints: bool,
-    pub toggle_debug_physics_aabbs: bool,
-    pub toggle_debug_physics_transforms: bool,
-    pub toggle_debug_physics_contacts: bool
+    pub save_snapshot: bool,
+    pub load_snapshot: bool,
+    pub rewind: bool,
+    pub menu: bool,
+    pub confirm: bool,
+    pub menu_up: bool,
+    pub menu_down: bool,
+    pub toggle_profiler: bool,
+    pub toggle_vsync: bool,
+    pub zoom_in: bool,
+    pub zoom_out: bool,
+    pub slow_down: bool,
+    pub speed_up: bool,
+    pub toggle_console: bool,
+    pub toggle_editor: bool,
+    pub save_level: bool,
+    pub next_glyph: bool,
+    pub previous_glyph: bool,
+    /// The mouse cursor in pixels from the top left corner of the screen, if it's over the window.
+    pub cursor: Option<Vec2>,
+    /// Set while the left mouse button is held, and `start_drag` when it's pressed.
+    pub drag: bool,
+    pub start_drag: bool,
+    /// Set while the right mouse button is held.
+    pub erase: bool
 }
 
 pub enum DebugShape {
@@ -47,42 +67,221 @@ bitflags! {
     }
 }
 
+/// The parts of a frame that are timed by the profiler.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProfileSection {
+    Events,
+    UpdateCat,
+    PhysicsStep,
+    NoodleCatUpdate,
+    Vertices,
+    Submit
+}
+
+pub const PROFILE_SECTIONS: usize = 6;
+/// How many frames the profiler keeps.
+pub const PROFILE_FRAMES: usize = 240;
+
+pub struct Profile {
+    /// The time spent in each section so far in this frame.
+    pub current: [time::Duration; PROFILE_SECTIONS],
+    /// The seconds spent in each section in recent frames, oldest first.
+    pub frames: VecDeque<[f32; PROFILE_SECTIONS]>
+}
+
 pub struct DebugInfo {
     pub shapes: VecDeque<(DebugShape, DebugColor)>,
     pub frames: VecDeque<time::Instant>,
     pub skipped_steps: bool,
     pub paused: bool,
+    pub rewinding: bool,
+    pub time_scale: f32,
     pub show_physics: bool,
-    pub physics_flags: DebugPhysics
+    pub physics_flags: DebugPhysics,
+    pub show_profiler: bool,
+    pub profile: Profile,
+    /// Why the last hot reload of a changed file failed.
+    pub reload_error: Option<String>
+}
+
+/// Where the level was last drawn from, for converting screen coordinates to world coordinates.
+pub struct Camera {
+    pub position: Vec2,
+    /// Half screen heights per world unit.
+    pub zoom: f32
+}
+
+/// The size of the framebuffer in pixels.
+pub struct Screen {
+    pub width: i32,
+    pub height: i32
+}
+
+/// How many lines of output the console keeps.
+pub const CONSOLE_LINES: usize = 12;
+
+pub struct Console {
+    pub open: bool,
+    /// The command being typed.
+    pub input: String,
+    /// Commands and their output, oldest first.
+    pub lines: VecDeque<String>,
+    /// Commands that were run, oldest first, and the one recalled into the input, if any.
+    pub commands: Vec<String>,
+    pub recalled: Option<usize>,
+    /// Set when the console is opened, so that the character typed by the toggle key is ignored.
+    pub skip_char: bool
+}
+
+/// The level editor, which stops the simulation while it's open.
+pub struct Editor {
+    pub open: bool,
+    /// The glyph placed with the left mouse button, and what it places.
+    pub glyph: char,
+    pub glyph_name: &'static str,
+    /// The tile under the cursor, if it can be edited.
+    pub cursor: Option<Vec2>,
+    /// Where the level is drawn from while the editor is open, instead of the cat.
+    pub camera: Vec2,
+    /// The spawn of the level, which is only shown in the editor.
+    pub spawn: Vec2
 }
 
 pub struct GUI {
-    pub cat_energy: u32
+    pub cat_energy: u32,
+    /// The seconds spent in the level so far, and the best time of a completed level.
+    pub level_time: f32,
+    pub best_time: Option<f32>
+}
+
+/// Ground tiles that behave differently from boxes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    /// A thin platform at the top of the tile that can be passed from below.
+    OneWay,
+    /// Ground that the cat can only hold onto from above.
+    Ice,
+    /// Ground that the cat holds onto from further away, and only lets go of when turning or extending.
+    Sticky,
+    /// Ends the game when the cat touches them.
+    Spikes,
+    /// Pushes the bodies in it up and slows them down.
+    Water
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PropShape {
+    /// A circle with a radius.
+    Circle(f32),
+    /// A box with a width and a height.
+    Box(f32, f32)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BodyType {
+    Static,
+    Kinematic,
+    Dynamic
+}
+
+/// How to create a prop, as defined by a level.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PropDef {
+    pub shape: PropShape,
+    pub body_type: BodyType,
+    pub density: f32,
+    pub friction: f32,
+    /// The velocity that the prop starts with.
+    pub velocity: Vec2,
+    pub angular_velocity: f32,
+    /// The tint of the sprite.
+    pub color: (u8, u8, u8)
+}
+
+/// Where a prop is, for drawing it.
+pub struct Prop {
+    pub shape: PropShape,
+    pub color: (u8, u8, u8),
+    pub position: Vec2,
+    pub angle: f32,
+    pub previous_position: Vec2,
+    pub previous_angle: f32
 }
 
 pub struct Ground {
     pub boxes: Vec<Vec2>,
+    pub tiles: Vec<(Vec2, Tile)>,
+    /// Food that hasn't been eaten yet.
+    pub food: Vec<Vec2>,
+    pub exit: Option<Vec2>,
+    /// Where the props start. Props are only created along with the physics world, so they don't change with the
+    /// ground.
+    pub props: Vec<(Vec2, PropDef)>,
     pub dirty: DirtyFlags
 }
 
+/// How quickly the cat follows the requested heading and speed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Steering {
+    pub turn_rate: f32,
+    pub acceleration: f32,
+    pub deceleration: f32
+}
+
 pub struct Cat {
     pub direction: Option<f32>,
+    pub speed: f32,
     pub turning: bool,
     pub extending: bool,
     pub contracting: bool,
     pub flying: bool,
     pub path: VecDeque<Vec2>,
     pub tail: VecDeque<Vec2>,
+    pub previous_path: VecDeque<Vec2>,
+    pub previous_tail: VecDeque<Vec2>,
     pub grab_d: Option<Vec2>,
     pub walk_phase: f32,
     pub energy: u32
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scene {
+    Title,
+    Gameplay,
+    PauseMenu,
+    LevelComplete,
+    GameOver
+}
+
+/// The menu of the active scene, which is empty during gameplay.
+pub struct Menu {
+    pub title: &'static str,
+    pub items: Vec<&'static str>,
+    pub selected: usize
+}
+
+/// The slowest and fastest the simulation can run relative to real time.
+pub const TIME_SCALE_RANGE: (f32, f32) = (0.1, 4.0);
+/// The time scales that slowing down and speeding up step through.
+pub const TIME_SCALES: [f32; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0];
+
 pub struct State {
+    pub scenes: Vec<Scene>,
+    pub menu: Menu,
     pub paused: bool,
+    pub rewinding: bool,
+    /// How fast the simulation runs relative to real time. The step time stays the same.
+    pub time_scale: f32,
     pub input: Input,
     pub debug: DebugInfo,
+    pub screen: Screen,
+    pub camera: Camera,
+    pub console: Console,
+    pub editor: Editor,
     pub gui: GUI,
+    pub steering: Steering,
     pub ground: Ground,
+    /// The props in the physics world, in the order they were created.
+    pub props: Vec<Prop>,
     pub cat: Cat
 }
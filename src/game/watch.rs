@@ -0,0 +1,57 @@
+use std::{
+    fs,
+    time,
+    path::{Path, PathBuf}
+};
+
+
+/// How often the modification times of the watched files are checked.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+/// Polls files for changes to their modification time.
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<time::SystemTime>)>,
+    last_poll: time::Instant
+}
+
+
+impl Watcher {
+    pub fn new() -> Watcher {
+        Watcher {
+            files: Vec::new(),
+            last_poll: time::Instant::now()
+        }
+    }
+
+
+    /// Starts watching a file. It doesn't have to exist yet.
+    pub fn watch<P: Into<PathBuf>>(&mut self, file: P) {
+        let file = file.into();
+        let modified = Self::modified(&file);
+        self.files.push((file, modified));
+    }
+
+
+    /// Returns the files that were changed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = time::Instant::now();
+        let mut changed = Vec::new();
+        for (file, modified) in &mut self.files {
+            // Editors may briefly remove a file while saving it, which isn't a change to reload.
+            let new_modified = Self::modified(file);
+            if new_modified.is_some() && new_modified != *modified {
+                *modified = new_modified;
+                changed.push(file.clone());
+            }
+        }
+        changed
+    }
+
+
+    fn modified(file: &Path) -> Option<time::SystemTime> {
+        fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    time,
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex
+};
+
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+pub struct LogError {
+    error: String
+}
+
+/// A log file that is moved to `<file>.1` when it grows too large, keeping a few older files around.
+struct LogFile {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+    max_size: u64,
+    backups: u32
+}
+
+struct Logger {
+    level: Level,
+    /// Module path prefixes with their own levels. The longest matching prefix wins.
+    filters: Vec<(String, Level)>,
+    file: Option<LogFile>
+}
+
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    level: Level::Info,
+    filters: Vec::new(),
+    file: None
+});
+
+
+/// Logs a message from the calling module if its level is enabled.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level, module_path!()) {
+            $crate::log::log($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+
+impl Error for LogError {}
+
+
+impl fmt::Debug for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None
+        }
+    }
+
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE"
+        }
+    }
+}
+
+
+impl Logger {
+    fn level(&self, module: &str) -> Level {
+        // Filters can be given with or without the crate name, e.g. `game::physics` or `lib::rgl`.
+        let without_crate = module.split_once("::").map_or("", |(_, path)| path);
+        let matches = |filter: &str, module: &str| {
+            module.starts_with(filter) && (module.len() == filter.len() || module[filter.len()..].starts_with("::"))
+        };
+        self.filters.iter()
+            .filter(|(filter, _)| matches(filter, module) || matches(filter, without_crate))
+            .max_by_key(|(filter, _)| filter.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+
+impl LogFile {
+    fn open(path: PathBuf, max_size: u64, backups: u32) -> Result<LogFile, io::Error> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path, file, size, max_size, backups })
+    }
+
+
+    fn write(&mut self, line: &str) -> Result<(), io::Error> {
+        if self.size + line.len() as u64 > self.max_size && self.size > 0 {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+
+    fn rotate(&mut self) -> Result<(), io::Error> {
+        let backup = |n: u32| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+        if self.backups == 0 {
+            self.file = fs::File::create(&self.path)?;
+        }
+        else {
+            for n in (1..self.backups).rev() {
+                if backup(n).exists() {
+                    fs::rename(backup(n), backup(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, backup(1))?;
+            self.file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+
+/// Sets the level of modules that don't match any filter.
+pub fn set_level(level: Level) {
+    LOGGER.lock().unwrap().level = level;
+}
+
+
+/// Sets the level of a module and its submodules.
+pub fn set_module_level(module: &str, level: Level) {
+    let mut logger = LOGGER.lock().unwrap();
+    logger.filters.retain(|(filter, _)| filter != module);
+    logger.filters.push((module.to_string(), level));
+}
+
+
+/// Applies a filter like `info,game::physics=debug,rgl=trace`, where a bare level sets the default level.
+pub fn set_filter(spec: &str) -> Result<(), LogError> {
+    let mut default = None;
+    let mut filters = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let error = || LogError { error: format!("invalid log filter '{}'", part) };
+        let mut parts = part.splitn(2, '=');
+        let first = parts.next().unwrap();
+        match parts.next() {
+            Some(level) => filters.push((first, Level::from_name(level).ok_or_else(error)?)),
+            None => default = Some(Level::from_name(first).ok_or_else(error)?)
+        }
+    }
+    if let Some(level) = default {
+        set_level(level);
+    }
+    for (module, level) in filters {
+        set_module_level(module, level);
+    }
+    Ok(())
+}
+
+
+/// Also writes the log to a file, which is rotated when it grows past `max_size` bytes.
+pub fn set_file(path: PathBuf, max_size: u64, backups: u32) -> Result<(), io::Error> {
+    let file = LogFile::open(path, max_size, backups)?;
+    LOGGER.lock().unwrap().file = Some(file);
+    Ok(())
+}
+
+
+pub fn enabled(level: Level, module: &str) -> bool {
+    level <= LOGGER.lock().unwrap().level(module)
+}
+
+
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    let time = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap_or_default();
+    let line = format!(
+        "[{}.{:03}] {:5} {}: {}\n",
+        time.as_secs(), time.subsec_millis(), level.name(), module, args
+    );
+    eprint!("{}", line);
+    let mut logger = LOGGER.lock().unwrap();
+    if let Some(file) = &mut logger.file {
+        if let Err(error) = file.write(&line) {
+            eprintln!("failed to write the log file: {}", error);
+            logger.file = None;
+        }
+    }
+}
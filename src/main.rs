@@ -1,24 +1,85 @@
 #[macro_use]
 extern crate bitflags;
 extern crate glfw;
+#[macro_use]
+extern crate lib;
 mod game;
+mod options;
 
 use std::{
+    env,
     panic,
+    process,
     ptr,
     ffi::CStr,
     thread,
+    time,
+    path::PathBuf,
     sync::{mpsc, Mutex}
 };
 
 use glfw::Context;
 
-use game::Game;
+use lib::log;
+use lib::math::{Vec2, vec2};
+
+use game::{Game, GameError, ErrorKind, Settings};
+use options::Options;
+
+
+/// The last polled gamepad state, so that only changes are sent to the game.
+struct Gamepad {
+    buttons: Vec<glfw::Action>,
+    stick: Vec2
+}
+
+/// Where the window was before switching to fullscreen, so that it can be put back.
+struct WindowPlacement {
+    position: (i32, i32),
+    size: (i32, i32)
+}
 
 
 fn main() {
-    let (panic_sender, panic_receiver) = mpsc::channel();
-    let panic_sender = Mutex::new(panic_sender);
+    let options = options::parse();
+    // Errors are reported through the log, so there is always a log file to find them in afterwards.
+    let log_file = match &options.log_file {
+        Some(file) => Some(PathBuf::from(file)),
+        None => env::current_exe().ok().map(|exe| exe.with_file_name("game.log"))
+    };
+    if let Some(file) = log_file {
+        if let Err(error) = log::set_file(file.clone(), 1 << 20, 3) {
+            eprintln!("failed to open the log file {}: {}", file.display(), error);
+        }
+    }
+    if options.headless {
+        if let Err(error) = run_headless(&options) {
+            error.report();
+            process::exit(1);
+        }
+        return;
+    }
+
+    // A broken settings file is left alone, instead of being overwritten with the defaults on exit.
+    let (mut settings, save_settings) = match Settings::load() {
+        Ok(settings) => (settings, true),
+        Err(error) => {
+            GameError::new(ErrorKind::Config, "loading the settings", error).report();
+            (Settings::default(), false)
+        }
+    };
+    // Command line overrides are only for this run, so the values from the file are kept for saving.
+    let file_values = if save_settings { Some((settings.window_size, settings.vsync)) } else { None };
+    if let Some(size) = options.size {
+        settings.window_size = size;
+    }
+    if let Some(vsync) = options.vsync {
+        settings.vsync = vsync;
+    }
+
+    // Stops the main loop when the game thread panics or quits.
+    let (exit_sender, exit_receiver) = mpsc::channel();
+    let panic_sender = Mutex::new(exit_sender.clone());
     let default_panic = panic::take_hook();
     panic::set_hook(Box::new(move |info| {
         default_panic(info);
@@ -28,44 +89,168 @@ fn main() {
 
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
 
-    glfw.window_hint(glfw::WindowHint::Resizable(false));
+    glfw.window_hint(glfw::WindowHint::Resizable(true));
     glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
     glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
     glfw.window_hint(glfw::WindowHint::DepthBits(Some(0)));
     glfw.window_hint(glfw::WindowHint::StencilBits(Some(0)));
 
-    let (mut window, events) = glfw.create_window(1280, 720, "CAT NOODLE!", glfw::WindowMode::Windowed).unwrap();
+    let (width, height) = settings.window_size;
+    let (mut window, events) = glfw.create_window(width, height, "CAT NOODLE!", glfw::WindowMode::Windowed).unwrap();
     window.set_key_polling(true);
+    window.set_char_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_cursor_enter_polling(true);
+    window.set_size_polling(true);
+    window.set_framebuffer_size_polling(true);
+    let mut windowed = None;
+    if settings.fullscreen {
+        toggle_fullscreen(&mut glfw, &mut window, &mut windowed);
+        settings.fullscreen = windowed.is_some();
+    }
 
     window.make_current();
     gl::load_with(|p| window.get_proc_address(p) as *const _);
-    glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
+    glfw.set_swap_interval(if settings.vsync { glfw::SwapInterval::Sync(1) } else { glfw::SwapInterval::None });
     glfw.make_context_current(None);
     handle_glfw_error();
 
+    // Maps GLFW event times to instants, so that the game can apply events on the right step.
+    let epoch = (time::Instant::now(), glfw.get_time());
+
     let (event_sender, event_receiver) = mpsc::channel();
+    let (width, height) = window.get_framebuffer_size();
+    event_sender.send(game::Event::Resize(width, height)).ok();
     let mut context = window.render_context();
     let game_thread = thread::Builder::new().name("Game".to_string()).spawn(move || {
         context.make_current();
         handle_glfw_error();
-        let mut game = Game::new(event_receiver).unwrap();
-        while game.update().unwrap() {
-            context.swap_buffers();
-            handle_glfw_error();
-        }
+        let result = run_game(&options, settings, file_values, event_receiver, &mut context);
+        // Lets the main thread take over the context to show an error screen.
+        unsafe { glfw::ffi::glfwMakeContextCurrent(ptr::null_mut()); }
+        exit_sender.send(()).ok();
+        unsafe { glfw::ffi::glfwPostEmptyEvent(); }
+        result
     }).unwrap();
 
-    while !window.should_close() && panic_receiver.try_recv().is_err() {
-        glfw.wait_events();
-        for (_, event) in glfw::flush_messages(&events) {
-            handle_event(event, &mut window, &event_sender);
+    let mut gamepad = Gamepad {
+        buttons: vec![glfw::Action::Release; glfw::ffi::GAMEPAD_BUTTON_LAST as usize + 1],
+        stick: vec2(0.0, 0.0)
+    };
+    while !window.should_close() && exit_receiver.try_recv().is_err() {
+        // Gamepads don't generate events, so they have to be polled.
+        glfw.wait_events_timeout(1.0 / 240.0);
+        poll_gamepad(&glfw, &mut gamepad, &event_sender);
+        for (event_time, event) in glfw::flush_messages(&events) {
+            let event_time = epoch.0 + time::Duration::from_secs_f64((event_time - epoch.1).max(0.0));
+            handle_event(event, event_time, &mut glfw, &mut window, &mut windowed, &event_sender);
         }
         handle_glfw_error();
     }
 
     event_sender.send(game::Event::Close).ok();
-    game_thread.join().unwrap();
+    if let Err(error) = game_thread.join().unwrap() {
+        error.report();
+        // A GL error may have left the context unusable.
+        if error.kind() != ErrorKind::GL && !window.should_close() {
+            show_error_screen(&mut glfw, &mut window, &events, &error);
+        }
+    }
+}
+
+
+fn run_game(
+    options: &Options,
+    settings: Settings,
+    file_values: Option<((u32, u32), bool)>,
+    event_receiver: mpsc::Receiver<game::Event>,
+    context: &mut glfw::RenderContext
+) -> Result<(), GameError> {
+    let mut game = Game::new(&options.game, settings, event_receiver)?;
+    setup_replay(&mut game, options)?;
+    let mut vsync = game.settings().vsync;
+    while game.update()? {
+        context.swap_buffers();
+        handle_glfw_error();
+        if game.settings().vsync != vsync {
+            vsync = game.settings().vsync;
+            // The swap interval belongs to the context, which is current on this thread.
+            unsafe { glfw::ffi::glfwSwapInterval(if vsync { 1 } else { 0 }); }
+        }
+    }
+    // A broken settings file has no values, and is left alone.
+    if let Some((window_size, vsync)) = file_values {
+        // Overridden settings are saved only if they were changed in the game.
+        let settings = game.settings_mut();
+        if options.size == Some(settings.window_size) {
+            settings.window_size = window_size;
+        }
+        if options.vsync == Some(settings.vsync) {
+            settings.vsync = vsync;
+        }
+        settings.save().map_err(|error| GameError::new(ErrorKind::Config, "saving the settings", error))?;
+    }
+    Ok(())
+}
+
+
+fn setup_replay(game: &mut Game, options: &Options) -> Result<(), GameError> {
+    if let Some(file) = &options.record {
+        game.record(file)?;
+    }
+    if let Some(file) = &options.play {
+        game.play(file)?;
+    }
+    Ok(())
+}
+
+
+fn run_headless(options: &Options) -> Result<(), GameError> {
+    let (_event_sender, event_receiver) = mpsc::channel();
+    // Like the windowed game, a broken settings file is reported and the defaults are used.
+    let settings = Settings::load().unwrap_or_else(|error| {
+        GameError::new(ErrorKind::Config, "loading the settings", error).report();
+        Settings::default()
+    });
+    let mut game = Game::new_headless(&options.game, settings, event_receiver)?;
+    setup_replay(&mut game, options)?;
+    let start = time::Instant::now();
+    game.simulate(options.steps)?;
+    println!("simulated {} steps in {:.3} s", options.steps, start.elapsed().as_secs_f32());
+    Ok(())
+}
+
+
+fn show_error_screen(
+    glfw: &mut glfw::Glfw,
+    window: &mut glfw::Window,
+    events: &mpsc::Receiver<(f64, glfw::WindowEvent)>,
+    error: &GameError
+) {
+    window.make_current();
+    let mut screen = match game::ErrorScreen::new() {
+        Ok(screen) => screen,
+        Err(screen_error) => {
+            error!("failed to show the error screen: {}", screen_error);
+            return;
+        }
+    };
+    while !window.should_close() {
+        let (width, height) = window.get_framebuffer_size();
+        if let Err(screen_error) = screen.render(error, width, height) {
+            error!("failed to show the error screen: {}", screen_error);
+            return;
+        }
+        window.swap_buffers();
+        glfw.wait_events();
+        for (_, event) in glfw::flush_messages(events) {
+            if let glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) = event {
+                window.set_should_close(true);
+            }
+        }
+    }
 }
 
 
@@ -79,16 +264,107 @@ fn handle_glfw_error() {
 }
 
 
-fn handle_event(event: glfw::WindowEvent, window: &mut glfw::Window, sender: &mpsc::Sender<game::Event>) {
+fn poll_gamepad(glfw: &glfw::Glfw, gamepad: &mut Gamepad, sender: &mpsc::Sender<game::Event>) {
+    let time = time::Instant::now();
+    let state = (0..).map(glfw::JoystickId::from_i32)
+        .take_while(Option::is_some)
+        .flatten()
+        .map(|id| glfw.get_joystick(id))
+        .find(|joystick| joystick.is_gamepad())
+        .and_then(|joystick| joystick.get_gamepad_state());
+
+    // A disconnected gamepad releases everything.
+    let buttons = (0..).map(glfw::GamepadButton::from_i32)
+        .take_while(Option::is_some)
+        .flatten();
+    for (button, previous) in buttons.zip(gamepad.buttons.iter_mut()) {
+        let action = match &state {
+            Some(state) => state.get_button_state(button),
+            None => glfw::Action::Release
+        };
+        if action != *previous {
+            *previous = action;
+            sender.send(game::Event::GamepadButton(action, button, time)).ok();
+        }
+    }
+
+    // The y axis of GLFW points down.
+    let stick = match &state {
+        Some(state) => vec2(
+            state.get_axis(glfw::GamepadAxis::AxisLeftX),
+            -state.get_axis(glfw::GamepadAxis::AxisLeftY)
+        ),
+        None => vec2(0.0, 0.0)
+    };
+    if (stick - gamepad.stick).length() > 1.0 / 256.0 {
+        gamepad.stick = stick;
+        sender.send(game::Event::GamepadStick(stick, time)).ok();
+    }
+}
+
+
+fn toggle_fullscreen(glfw: &mut glfw::Glfw, window: &mut glfw::Window, windowed: &mut Option<WindowPlacement>) {
+    if let Some(placement) = windowed.take() {
+        let ((x, y), (width, height)) = (placement.position, placement.size);
+        window.set_monitor(glfw::WindowMode::Windowed, x, y, width as _, height as _, None);
+        return;
+    }
+    glfw.with_primary_monitor_mut(|_, monitor| {
+        let monitor = match monitor {
+            Some(monitor) => monitor,
+            None => return
+        };
+        if let Some(mode) = monitor.get_video_mode() {
+            *windowed = Some(WindowPlacement {
+                position: window.get_pos(),
+                size: window.get_size()
+            });
+            window.set_monitor(
+                glfw::WindowMode::FullScreen(monitor), 0, 0, mode.width, mode.height, Some(mode.refresh_rate)
+            );
+        }
+    });
+}
+
+
+fn handle_event(
+    event: glfw::WindowEvent,
+    time: time::Instant,
+    glfw: &mut glfw::Glfw,
+    window: &mut glfw::Window,
+    windowed: &mut Option<WindowPlacement>,
+    sender: &mpsc::Sender<game::Event>
+) {
     match event {
-        glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
-            window.set_should_close(true);
+        glfw::WindowEvent::Key(glfw::Key::F11, _, glfw::Action::Press, _) => {
+            toggle_fullscreen(glfw, window, windowed);
+            sender.send(game::Event::Fullscreen(windowed.is_some())).ok();
+        }
+        // Only the windowed size is kept, so that leaving fullscreen restores it.
+        glfw::WindowEvent::Size(width, height) if windowed.is_none() && width > 0 && height > 0 => {
+            sender.send(game::Event::WindowSize(width as u32, height as u32)).ok();
+        }
+        glfw::WindowEvent::FramebufferSize(width, height) => {
+            sender.send(game::Event::Resize(width, height)).ok();
+        }
+        glfw::WindowEvent::Key(key, _, action, _) => {
+            sender.send(game::Event::Key(action, key, time)).ok();
+        }
+        glfw::WindowEvent::Char(c) => {
+            sender.send(game::Event::Char(c, time)).ok();
+        }
+        glfw::WindowEvent::MouseButton(button, action, _) => {
+            sender.send(game::Event::MouseButton(action, button, time)).ok();
         }
-        glfw::WindowEvent::Key(key, _, glfw::Action::Press, _) => {
-            sender.send(game::Event::Key(glfw::Action::Press, key)).ok();
+        glfw::WindowEvent::CursorPos(x, y) => {
+            // The cursor is in screen coordinates, which differ from pixels on high DPI screens.
+            let (width, _) = window.get_size();
+            let (framebuffer_width, _) = window.get_framebuffer_size();
+            let scale = if width > 0 { framebuffer_width as f32 / width as f32 } else { 1.0 };
+            sender.send(game::Event::CursorPos(vec2(x as f32, y as f32) * scale, time)).ok();
         }
-        glfw::WindowEvent::Key(key, _, glfw::Action::Release, _) => {
-            sender.send(game::Event::Key(glfw::Action::Release, key)).ok();
+        glfw::WindowEvent::CursorEnter(false) => {
+            sender.send(game::Event::CursorLeft).ok();
         }
         _ => {}
     }
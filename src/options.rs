@@ -0,0 +1,134 @@
+use std::{
+    env,
+    process,
+    str::FromStr
+};
+
+use lib::log;
+
+use super::game;
+
+
+/// The fastest physics step rate, as listed in `USAGE`.
+const MAX_STEP_RATE: u32 = 4000;
+
+const USAGE: &str = "\
+Usage: cat-noodle [OPTIONS]
+
+Options:
+    --level FILE          Play the level in FILE instead of the campaign
+    --levels DIR          Play the campaign in DIR, levels by default
+    --size WIDTHxHEIGHT   Window size, overriding the settings file
+    --vsync on|off        Wait for vertical sync when presenting frames, overriding the settings file
+    --step-rate N         Physics steps per second from 1 to 4000, 480 by default
+    --cat-length N        Number of links the cat starts with, 30 by default
+    --record FILE         Record input events to FILE
+    --play FILE           Play back input events from FILE
+    --headless            Simulate without a window and exit
+    --steps N             Number of steps to simulate when headless, 4800 by default
+    --log FILTER          Log levels like debug or info,game::physics=trace,rgl=debug
+    --log-file FILE       Write the log to FILE instead of game.log next to the executable, rotated at 1 MB
+    --help                Print this message
+";
+
+
+pub struct Options {
+    pub size: Option<(u32, u32)>,
+    pub vsync: Option<bool>,
+    pub headless: bool,
+    pub steps: u64,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub log_file: Option<String>,
+    pub game: game::Config
+}
+
+
+/// Parses the command line, or exits with a usage message if it's invalid.
+pub fn parse() -> Options {
+    let mut options = Options {
+        size: None,
+        vsync: None,
+        headless: false,
+        steps: 4800,
+        record: None,
+        play: None,
+        log_file: None,
+        game: game::Config::default()
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--level" => options.game.level = Some(value(&arg, args.next(), "a file")),
+            "--levels" => options.game.levels = value(&arg, args.next(), "a directory"),
+            "--size" => {
+                let size = value(&arg, args.next(), "a size like 1280x720");
+                let mut parts = size.splitn(2, 'x').map(str::parse);
+                match (parts.next(), parts.next()) {
+                    (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => {
+                        options.size = Some((width, height));
+                    }
+                    _ => fail(&format!("{} expects a size like 1280x720", arg))
+                }
+            }
+            "--vsync" => {
+                options.vsync = match value(&arg, args.next(), "on or off").as_str() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => fail(&format!("{} expects on or off", arg))
+                };
+            }
+            "--step-rate" => {
+                options.game.step_rate = number(&arg, args.next());
+                // Faster rates make the step time round to nothing and the rewind history huge.
+                if options.game.step_rate == 0 || options.game.step_rate > MAX_STEP_RATE {
+                    fail(&format!("{} must be from 1 to {}", arg, MAX_STEP_RATE));
+                }
+            }
+            "--cat-length" => {
+                options.game.cat_length = number(&arg, args.next());
+                if options.game.cat_length < 3 {
+                    fail(&format!("{} must be at least 3", arg));
+                }
+            }
+            "--record" => options.record = Some(value(&arg, args.next(), "a file")),
+            "--play" => options.play = Some(value(&arg, args.next(), "a file")),
+            "--headless" => options.headless = true,
+            "--steps" => options.steps = number(&arg, args.next()),
+            "--log" => {
+                if let Err(error) = log::set_filter(&value(&arg, args.next(), "a log filter")) {
+                    fail(&format!("{}: {}", arg, error));
+                }
+            }
+            "--log-file" => options.log_file = Some(value(&arg, args.next(), "a file")),
+            "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => fail(&format!("unknown argument: {}", arg))
+        }
+    }
+    options
+}
+
+
+fn value(arg: &str, value: Option<String>, expected: &str) -> String {
+    match value {
+        Some(value) => value,
+        None => fail(&format!("{} expects {}", arg, expected))
+    }
+}
+
+
+fn number<T: FromStr>(arg: &str, value: Option<String>) -> T {
+    match value.and_then(|value| value.parse().ok()) {
+        Some(number) => number,
+        None => fail(&format!("{} expects a number", arg))
+    }
+}
+
+
+fn fail(error: &str) -> ! {
+    eprintln!("{}\n\n{}", error, USAGE);
+    process::exit(2);
+}
@@ -83,6 +83,7 @@ fn handle_error(function: &str) -> Result<(), GLError> {
         gl::OUT_OF_MEMORY => "OUT_OF_MEMORY",
         _ => "UNKNOWN"
     };
+    crate::error!("gl{} failed ({})", function, error);
     Err(GLError { error: format!("{} failed ({})", function, error) })
 }
 
@@ -123,6 +124,13 @@ pub fn clear(r: f32, g: f32, b: f32, a: f32) -> Result<(), GLError> {
 }
 
 
+pub fn viewport(x: i32, y: i32, width: i32, height: i32) -> Result<(), GLError> {
+    unsafe { gl::Viewport(x, y, width, height); }
+    handle_error("Viewport")?;
+    Ok(())
+}
+
+
 pub fn draw(mode: DrawMode, start: i32, count: i32) -> Result<(), GLError> {
     let mode = match mode {
         DrawMode::Triangles => gl::TRIANGLES,